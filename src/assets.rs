@@ -0,0 +1,24 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+/// Maps the url of a static file to the url of its fingerprinted version,
+/// e.g. /css/post.min.css => /css/post.3fa9c1.min.css
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetManifest {
+    urls: BTreeMap<String, String>,
+}
+
+impl AssetManifest {
+    pub fn insert(&mut self, url: String, fingerprinted: String) {
+        self.urls.insert(url, fingerprinted);
+    }
+    
+    /// Returns the fingerprinted url of a static file or the url itself
+    /// if the file is not fingerprinted
+    pub fn url(&self, url: &str) -> String {
+        match self.urls.get(url) {
+            Some(fingerprinted) => fingerprinted.clone(),
+            None => url.to_string(),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::posts::{Post, PostDate};
+
+const AUTHOR: &str = "z2";
+const SITE_NAME: &str = "z2's blog";
+const SITE_URL: &str = "https://z2-2z.github.io";
+
+/// Characters that have a special meaning in BibTeX
+const BIBTEX_SPECIAL: [(char, &str); 10] = [
+    ('\\', "\\textbackslash{}"),
+    ('{', "\\{"),
+    ('}', "\\}"),
+    ('&', "\\&"),
+    ('%', "\\%"),
+    ('$', "\\$"),
+    ('#', "\\#"),
+    ('_', "\\_"),
+    ('~', "\\textasciitilde{}"),
+    ('^', "\\textasciicircum{}"),
+];
+
+pub fn bibtex_filename<P: AsRef<Path>>(page: P) -> PathBuf {
+    page.as_ref().with_extension("bib")
+}
+
+pub fn csl_filename<P: AsRef<Path>>(page: P) -> PathBuf {
+    page.as_ref().with_extension("csl.json")
+}
+
+fn escape_bibtex(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    
+    for c in text.chars() {
+        match BIBTEX_SPECIAL.iter().find(|(special, _)| *special == c) {
+            Some((_, escaped)) => output.push_str(escaped),
+            None => output.push(c),
+        }
+    }
+    
+    output
+}
+
+#[derive(Serialize)]
+struct CslName {
+    literal: &'static str,
+}
+
+#[derive(Serialize)]
+struct CslDate {
+    #[serde(rename = "date-parts")]
+    date_parts: [[u32; 3]; 1],
+}
+
+#[derive(Serialize)]
+struct CslItem<'a> {
+    id: &'a str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: &'a str,
+    author: [CslName; 1],
+    #[serde(rename = "container-title")]
+    container_title: &'static str,
+    issued: CslDate,
+    accessed: CslDate,
+    #[serde(rename = "URL")]
+    url: &'a str,
+    keyword: String,
+}
+
+/// The reference to a post in the formats of common reference managers
+pub struct Citation {
+    bibtex: String,
+    csl_json: String,
+}
+
+impl Citation {
+    /// The access date is the date of the last update of the post so that the citation
+    /// only changes with the post. `--now` overrides it.
+    pub fn new(post: &Post, accessed: Option<&PostDate>) -> Result<Self> {
+        let metadata = post.metadata();
+        let date = metadata.date();
+        let accessed = accessed.unwrap_or(metadata.updated());
+        let url = format!("{SITE_URL}{}", post.url());
+        let slug = Path::new(post.url()).file_stem().and_then(|x| x.to_str()).unwrap_or_default();
+        let key = format!("{AUTHOR}-{:04}-{slug}", date.year());
+        let keywords = metadata.categories().join(", ");
+        
+        let bibtex = format!(
+            "@online{{{key},\n    author = {{{AUTHOR}}},\n    title = {{{{{}}}}},\n    date = {{{:04}-{:02}-{:02}}},\n    url = {{{url}}},\n    urldate = {{{:04}-{:02}-{:02}}},\n    organization = {{{}}},\n    keywords = {{{}}},\n}}\n",
+            escape_bibtex(metadata.title()),
+            date.year(), date.month(), date.day(),
+            accessed.year(), accessed.month(), accessed.day(),
+            escape_bibtex(SITE_NAME),
+            escape_bibtex(&keywords),
+        );
+        
+        let item = CslItem {
+            id: &key,
+            kind: "post-weblog",
+            title: metadata.title(),
+            author: [CslName {
+                literal: AUTHOR,
+            }],
+            container_title: SITE_NAME,
+            issued: CslDate {
+                date_parts: [[date.year() as u32, date.month() as u32, date.day() as u32]],
+            },
+            accessed: CslDate {
+                date_parts: [[accessed.year() as u32, accessed.month() as u32, accessed.day() as u32]],
+            },
+            url: &url,
+            keyword: keywords,
+        };
+        let csl_json = serde_json::to_string_pretty(&[item])?;
+        
+        Ok(Self {
+            bibtex,
+            csl_json,
+        })
+    }
+    
+    pub fn bibtex(&self) -> &str {
+        &self.bibtex
+    }
+    
+    pub fn csl_json(&self) -> &str {
+        &self.csl_json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_citation() {
+        let post = Post::new("test-data/renderer/example.md", true).unwrap();
+        let citation = Citation::new(&post, Some(&PostDate::parse("05.03.2024").unwrap())).unwrap();
+        assert!(citation.bibtex().starts_with("@online{z2-3054-title-with-span-spaces-is-really-delicious,\n"));
+        assert!(citation.bibtex().contains("    urldate = {2024-03-05},\n"));
+        
+        /* Without --now the citation only changes with the post */
+        let updated = post.metadata().updated();
+        let citation = Citation::new(&post, None).unwrap();
+        assert!(citation.bibtex().contains(&format!("    urldate = {{{:04}-{:02}-{:02}}},\n", updated.year(), updated.month(), updated.day())));
+        assert_eq!(escape_bibtex("50% of C#_{x}"), "50\\% of C\\#\\_\\{x\\}");
+        
+        let csl: serde_json::Value = serde_json::from_str(citation.csl_json()).unwrap();
+        assert_eq!(csl[0]["issued"]["date-parts"][0][0], 3054);
+        assert_eq!(csl[0]["title"], post.metadata().title());
+    }
+}
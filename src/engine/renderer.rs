@@ -1,10 +1,16 @@
 use std::path::{Path, PathBuf};
-use std::collections::{HashSet, HashMap};
+use std::collections::{HashSet, HashMap, BTreeMap};
 use anyhow::Result;
 use pulldown_cmark as md;
 use askama::Template;
 
-use crate::{transformer, engine::templates::*, parser, posts::{Post, CacheEntry, PostDate}, net::http_url_exists};
+use crate::{transformer::{self, ImageInfo, ImageOptions, TransformOptions}, assets::AssetManifest, engine::templates::*, parser, posts::{Post, CacheEntry, PostDate, PostStats, PostChange, PostContext, Site}, net::http_url_exists, svg, og, cite};
+
+/// Subfigures are labeled from (a) to (z)
+const MAX_SUBFIGURES: usize = 26;
+
+/// Maximum length of an automatically extracted summary
+const SUMMARY_LEN: usize = 200;
 
 #[inline]
 fn append_template<T: Template>(output: &mut String, template: T) -> Result<()> {
@@ -12,6 +18,147 @@ fn append_template<T: Template>(output: &mut String, template: T) -> Result<()>
     Ok(())
 }
 
+/// Turns rendered html into text that can be used inside an attribute
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut inside_tag = false;
+    
+    for c in html.chars() {
+        match c {
+            '<' => inside_tag = true,
+            '>' => inside_tag = false,
+            c if !inside_tag => text.push(c),
+            _ => {},
+        }
+    }
+    
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Links to other posts point to their markdown source
+fn is_post_link(url: &str) -> bool {
+    let path = url.split('#').next().unwrap();
+    !url.contains("://") && !url.starts_with('/') && path.ends_with(".md")
+}
+
+/// The source file of the post that a link points to
+fn resolve_post_link(site: &Site, basedir: &Path, target: &str, wikilink: bool) -> Option<PathBuf> {
+    if wikilink {
+        site.find_title(target).map(Path::to_path_buf)
+    } else {
+        let input = basedir.join(target);
+        site.url(&input).map(|_| input)
+    }
+}
+
+/// The source files of all posts that the content links to, without rendering it.
+/// Links that cannot be resolved are left for the renderer to report.
+pub fn linked_posts(content: &[u8], basedir: &Path, site: &Site) -> Vec<PathBuf> {
+    let Ok(content) = std::str::from_utf8(content) else {
+        return Vec::new();
+    };
+    let mut options = md::Options::empty();
+    options.insert(md::Options::ENABLE_TABLES);
+    options.insert(md::Options::ENABLE_STRIKETHROUGH);
+    options.insert(md::Options::ENABLE_WIKILINKS);
+    
+    let mut links = Vec::new();
+    
+    for event in md::Parser::new_ext(content, options) {
+        if let md::Event::Start(md::Tag::Link { link_type, dest_url, .. }) = event {
+            let wikilink = matches!(link_type, md::LinkType::WikiLink { .. });
+            
+            if wikilink || is_post_link(&dest_url) {
+                let target = dest_url.split('#').next().unwrap();
+                links.extend(resolve_post_link(site, basedir, target, wikilink));
+            }
+        }
+    }
+    
+    links
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Decodes a single entity without the surrounding `&` and `;`
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => None,
+    }
+}
+
+/// Decodes all entities in one pass so that escaped entities stay literal text
+fn unescape_html(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        
+        let decoded = rest[1..].find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| decode_entity(&rest[1..1 + end]).map(|c| (c, end + 2)));
+        
+        match decoded {
+            Some((c, len)) => {
+                output.push(c);
+                rest = &rest[len..];
+            },
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    
+    output.push_str(rest);
+    output
+}
+
+/// Shortens text to at most SUMMARY_LEN characters at a word boundary
+fn summarize(text: &str) -> String {
+    if text.chars().count() <= SUMMARY_LEN {
+        return text.to_owned();
+    }
+    
+    let mut summary = String::with_capacity(SUMMARY_LEN + 3);
+    
+    for word in text.split(' ') {
+        if summary.chars().count() + word.chars().count() + 1 > SUMMARY_LEN {
+            break;
+        }
+        
+        if !summary.is_empty() {
+            summary.push(' ');
+        }
+        summary.push_str(word);
+    }
+    
+    summary.push('…');
+    summary
+}
+
 fn make_id(id: &str) -> String {
     let mut prev_dash = false;
     id.chars()
@@ -29,35 +176,109 @@ fn make_id(id: &str) -> String {
         .collect()
 }
 
+/// Applies the image settings of a <figure-title quality="..." lossless avif passthrough> tag
+fn parse_figure_attributes(tag: &str, options: &mut ImageOptions) -> Result<()> {
+    let attributes = tag.trim_start_matches("<figure-title").trim_end_matches('>');
+    
+    for attribute in attributes.split_whitespace() {
+        let (key, value) = match attribute.split_once('=') {
+            Some((key, value)) => (key, Some(value.trim_matches('"'))),
+            None => (attribute, None),
+        };
+        
+        match (key, value) {
+            ("quality", Some(value)) => match value.parse::<f32>() {
+                Ok(quality) if (0.0..=100.0).contains(&quality) => options.quality = quality,
+                _ => anyhow::bail!("Invalid image quality: {value}"),
+            },
+            ("lossless", None) => options.lossless = true,
+            ("lossy", None) => options.lossless = false,
+            ("avif", None) => options.avif = true,
+            ("passthrough", None) => options.passthrough = true,
+            _ => anyhow::bail!("Invalid attribute in {tag}"),
+        }
+    }
+    
+    Ok(())
+}
+
+/// Everything a post is rendered with that does not come from the post itself
+pub struct RenderParams<'a> {
+    /// The folder of the post that relative links are resolved against
+    pub basedir: &'a Path,
+    pub offline: bool,
+    pub strict: bool,
+    pub assets: &'a AssetManifest,
+    pub options: &'a TransformOptions,
+    pub site: &'a Site,
+    pub context: &'a PostContext,
+    /// Images whose source did not change since the post was last rendered
+    pub known_images: &'a HashMap<PathBuf, ImageInfo>,
+}
+
 #[derive(Debug)]
 pub struct Renderer<'a> {
     basedir: &'a Path,
+    assets: &'a AssetManifest,
+    options: &'a TransformOptions,
+    site: &'a Site,
+    context: &'a PostContext,
+    known_images: &'a HashMap<PathBuf, ImageInfo>,
     offline: bool,
+    strict: bool,
     uses_code: bool,
     table_cursor: usize,
     figure_cursor: usize,
     reference_cursor: usize,
+    code_lines: usize,
     p_level: usize,
     description: String,
     file_mentions: HashSet<PathBuf>,
+    images: HashMap<PathBuf, ImageOptions>,
+    image_info: BTreeMap<PathBuf, ImageInfo>,
+    figure_attributes: Option<String>,
     references: HashMap<String, usize>,
+    citations: Vec<String>,
+    links: BTreeMap<PathBuf, String>,
     languages: HashSet<String>,
+    headings: Vec<(String, String)>,
+    summary: String,
+    text: String,
+    index_text: bool,
 }
 
 impl<'a> Renderer<'a> {
-    pub fn new(basedir: &'a Path, offline: bool) -> Self {
+    pub fn new(params: RenderParams<'a>) -> Self {
+        let RenderParams { basedir, offline, strict, assets, options, site, context, known_images } = params;
+        
         Self {
             basedir,
+            assets,
+            options,
+            site,
+            context,
+            known_images,
             offline,
+            strict,
             uses_code: false,
             table_cursor: 1,
             figure_cursor: 1,
             reference_cursor: 1,
+            code_lines: 0,
             p_level: 0,
             description: String::new(),
             file_mentions: HashSet::new(),
+            images: HashMap::new(),
+            image_info: BTreeMap::new(),
+            figure_attributes: None,
             references: HashMap::new(),
+            citations: Vec::new(),
+            links: BTreeMap::new(),
             languages: HashSet::new(),
+            headings: Vec::new(),
+            summary: String::new(),
+            text: String::with_capacity(64 * 1024),
+            index_text: true,
         }
     }
     
@@ -65,10 +286,82 @@ impl<'a> Renderer<'a> {
         &self.file_mentions
     }
     
+    /// The settings with which a mentioned image must be transformed
+    pub fn image_options(&self, path: &Path) -> ImageOptions {
+        match self.images.get(path) {
+            Some(options) => options.clone(),
+            None => self.options.image.for_file(path),
+        }
+    }
+    
+    /// What the figures of the post used about each mentioned image
+    pub fn image_info(&self) -> &BTreeMap<PathBuf, ImageInfo> {
+        &self.image_info
+    }
+    
+    /// The name of a mentioned file in the output folder
+    pub fn output_filename(&self, path: &Path) -> PathBuf {
+        transformer::transform_filename(path, &self.image_options(path))
+    }
+    
+    pub fn context(&self) -> &PostContext {
+        self.context
+    }
+    
+    /// The source files of all linked posts and the urls that the links point to
+    pub fn links(&self) -> &BTreeMap<PathBuf, String> {
+        &self.links
+    }
+    
+    /// Resolves a link to another post by the path of its source file
+    /// or, for wikilinks, by its title
+    fn post_link(&mut self, dest_url: &str, wikilink: bool) -> Result<String> {
+        let (target, fragment) = match dest_url.split_once('#') {
+            Some((target, fragment)) => (target, Some(fragment)),
+            None => (dest_url, None),
+        };
+        
+        let Some(input) = resolve_post_link(self.site, self.basedir, target, wikilink) else {
+            if wikilink {
+                anyhow::bail!("Wikilink [[{target}]] does not match the title of any published post");
+            } else {
+                anyhow::bail!("Link to {dest_url} does not point to a published post");
+            }
+        };
+        let url = self.site.url(&input).unwrap();
+        
+        self.links.insert(input, url.to_owned());
+        
+        match fragment {
+            Some(fragment) => Ok(format!("{url}#{fragment}")),
+            None => Ok(url.to_owned()),
+        }
+    }
+    
     pub fn languages_used(&self) -> &HashSet<String> {
         &self.languages
     }
     
+    /// The description from the metadata or else the beginning of the first paragraph
+    pub fn summary<'b>(&'b self, post: &'b Post) -> &'b str {
+        post.metadata().description().unwrap_or(&self.summary)
+    }
+    
+    /// The plain text of the rendered post for the search index
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    
+    pub fn stats(&self) -> PostStats {
+        PostStats {
+            words: self.text.split_whitespace().count(),
+            code_lines: self.code_lines,
+            figures: self.figure_cursor - 1,
+            tables: self.table_cursor - 1,
+            citations: self.references.len(),
+        }
+    }
+    
     pub fn render_header(&self, post: &Post) -> Result<String> {
         let mut output = String::with_capacity(4096);
         
@@ -77,7 +370,20 @@ impl<'a> Renderer<'a> {
             uses_code: self.uses_code,
             languages: &self.languages,
             keywords: post.metadata().categories().join(", "),
-            url: post.url(),
+            categories: post.metadata().categories(),
+            url: absolute_url(post.url()),
+            published: atom_timestamp(post.metadata().date()),
+            updated: atom_timestamp(post.metadata().updated()),
+            unlisted: post.metadata().unlisted(),
+            /* Posts with a bibliography are marked as scientific articles */
+            article_type: if self.citations.is_empty() { "BlogPosting" } else { "ScholarlyArticle" },
+            citations: &self.citations,
+            summary: match self.summary(post) {
+                "" => post.metadata().title(),
+                summary => summary,
+            },
+            og_image: absolute_url(&og::filename(post.url()).to_string_lossy()),
+            assets: self.assets,
         })?;
         
         append_template(&mut output, Headline {
@@ -89,8 +395,33 @@ impl<'a> Renderer<'a> {
             day: post.metadata().date().day(),
             month: post.metadata().date().month_name(),
             year: post.metadata().date().year(),
+            reading_time: self.stats().reading_time(),
+            updated: match post.metadata().updated() {
+                updated if updated == post.metadata().date() => None,
+                updated => Some(format!("{:02} {} {:04}", updated.day(), updated.month_name(), updated.year())),
+            },
         })?;
         
+        if let Some(series) = &self.context.series {
+            append_template(&mut output, SeriesBox {
+                series,
+            })?;
+        }
+        
+        let changes: Vec<&PostChange> = post.metadata().changes().collect();
+        
+        if post.metadata().changelog() && !changes.is_empty() {
+            append_template(&mut output, Changelog {
+                changes: &changes,
+            })?;
+        }
+        
+        if post.metadata().toc() && !self.headings.is_empty() {
+            append_template(&mut output, TableOfContents {
+                headings: &self.headings,
+            })?;
+        }
+        
         Ok(output)
     }
     
@@ -100,6 +431,7 @@ impl<'a> Renderer<'a> {
         let mut options = md::Options::empty();
         options.insert(md::Options::ENABLE_TABLES);
         options.insert(md::Options::ENABLE_STRIKETHROUGH);
+        options.insert(md::Options::ENABLE_WIKILINKS);
         
         let mut parser = md::Parser::new_ext(content, options);
         
@@ -115,7 +447,11 @@ impl<'a> Renderer<'a> {
             md::Event::Start(tag) => match tag {
                 md::Tag::Paragraph => {
                     self.p_level += 1;
+                    let figures = self.figure_cursor;
                     let data = self.collect(parser)?;
+                    if self.p_level == 1 && self.summary.is_empty() && self.figure_cursor == figures {
+                        self.summary = summarize(&unescape_html(&strip_tags(&data)));
+                    }
                     if !data.is_empty() {
                         append_template(output, Paragraph {
                             content: &data,
@@ -133,6 +469,7 @@ impl<'a> Renderer<'a> {
                         content: &data,
                         id: &id,
                     })?;
+                    self.headings.push((id, strip_tags(&data)));
                 },
                 md::Tag::BlockQuote(_) => {
                     let data = self.collect(parser)?;
@@ -148,11 +485,14 @@ impl<'a> Renderer<'a> {
                             _ => language.as_ref().to_ascii_lowercase(),
                         }
                     };
+                    self.index_text = false;
                     let data = self.collect(parser)?;
+                    self.index_text = true;
                     append_template(output, Codeblock {
                         language: &language,
                         content: &data,
                     })?;
+                    self.code_lines += data.lines().count();
                     self.languages.insert(language);
                     self.uses_code = true;
                 },
@@ -221,6 +561,14 @@ impl<'a> Renderer<'a> {
                         content: &data,
                     })?;
                 },
+                md::Tag::Link { link_type, dest_url, .. } if matches!(link_type, md::LinkType::WikiLink { .. }) || is_post_link(&dest_url) => {
+                    let url = self.post_link(&dest_url, matches!(link_type, md::LinkType::WikiLink { .. }))?;
+                    let data = self.collect(parser)?;
+                    append_template(output, Link {
+                        url: &url,
+                        content: &data,
+                    })?;
+                },
                 md::Tag::Link { dest_url, .. } => {
                     let dest_url = dest_url.as_ref();
                     let data = self.collect(parser)?;
@@ -237,31 +585,20 @@ impl<'a> Renderer<'a> {
                 },
                 md::Tag::Image { dest_url, .. } => {
                     let dest_url = dest_url.as_ref();
-                    self.collect(parser)?;
-                    
-                    let mut path = self.basedir.join(dest_url);
-                    
-                    if path.exists() {
-                        self.file_mentions.insert(PathBuf::from(dest_url));
-                    } else if !self.offline && !http_url_exists(dest_url)? {
-                        println!("Warning: The specified URL {dest_url} seems to be invalid");
-                    }
+                    let alt = strip_tags(&self.collect(parser)?);
+                    let alt = self.alt_text(dest_url, &[&alt, &strip_tags(&self.description)])?;
                     
-                    let url = if path.exists() {
-                        path = transformer::transform_filename(dest_url);
-                        path.to_str().unwrap()
-                    } else {
-                        dest_url
-                    };
+                    let image = self.render_image(dest_url, alt, self.figure_cursor > 1)?;
                     
                     append_template(output, Figure {
                         number: self.figure_cursor,
-                        url,
+                        image: &image,
                         description: &self.description,
                         inside_p: self.p_level > 0,
                     })?;
                     self.figure_cursor += 1;
                     self.description.clear();
+                    self.figure_attributes = None;
                 },
                 md::Tag::HtmlBlock => {
                     let data = self.collect(parser)?;
@@ -279,6 +616,15 @@ impl<'a> Renderer<'a> {
                         let data = self.collect_html(parser, "</figure-title>")?;
                         self.description = data;
                     },
+                    tag if tag.starts_with("<figure-title ") => {
+                        self.figure_attributes = Some(tag.to_string());
+                        let data = self.collect_html(parser, "</figure-title>")?;
+                        self.description = data;
+                    },
+                    "<figure>" => {
+                        assert_eq!(self.p_level, 0);
+                        self.render_figure_group(parser, output)?;
+                    },
                     "<cite>" => {
                         let data = self.collect_html(parser, "</cite>")?;
                         let mut ids = Vec::new();
@@ -321,6 +667,11 @@ impl<'a> Renderer<'a> {
                         let data = self.collect_html(parser, "</figure-title>")?;
                         self.description = data;
                     },
+                    tag if tag.starts_with("<figure-title ") => {
+                        self.figure_attributes = Some(tag.to_string());
+                        let data = self.collect_html(parser, "</figure-title>")?;
+                        self.description = data;
+                    },
                     "<cite>" => {
                         let data = self.collect_html(parser, "</cite>")?;
                         let mut ids = Vec::new();
@@ -351,18 +702,24 @@ impl<'a> Renderer<'a> {
                 append_template(output, Tag {
                     content: content.as_ref(),
                 })?;
+                self.text.push_str(content.as_ref());
                 self.uses_code = true;
             },
             md::Event::Text(text) => {
                 append_template(output, Text {
                     content: text.as_ref(),
                 })?;
+                if self.index_text {
+                    self.text.push_str(text.as_ref());
+                }
             },
             md::Event::SoftBreak => {
                 output.push(' ');
+                self.text.push(' ');
             },
             md::Event::HardBreak => {
                 append_template(output, Linebreak {})?;
+                self.text.push(' ');
             },
             md::Event::Rule => {
                 assert_eq!(self.p_level, 0);
@@ -374,12 +731,149 @@ impl<'a> Renderer<'a> {
         Ok(())
     }
     
+    /// Returns the first non-empty alt text candidate and lints images without one
+    fn alt_text(&self, dest_url: &str, candidates: &[&str]) -> Result<String> {
+        if let Some(alt) = candidates.iter().find(|alt| !alt.is_empty()) {
+            return Ok(alt.to_string());
+        }
+        
+        if self.strict {
+            anyhow::bail!("Image {dest_url} has neither alt text nor a description");
+        }
+        
+        println!("Warning: Image {dest_url} has neither alt text nor a description");
+        Ok(String::new())
+    }
+    
+    /// Registers a mentioned image and computes everything the figure templates need
+    fn render_image(&mut self, dest_url: &str, alt: String, lazy: bool) -> Result<Image> {
+        let path = self.basedir.join(dest_url);
+        let mut options = self.options.image.for_file(dest_url);
+        
+        if let Some(attributes) = &self.figure_attributes {
+            parse_figure_attributes(attributes, &mut options)?;
+        }
+        
+        if path.exists() {
+            self.file_mentions.insert(PathBuf::from(dest_url));
+            self.images.insert(PathBuf::from(dest_url), options.clone());
+        } else if !self.offline && !http_url_exists(dest_url)? {
+            println!("Warning: The specified URL {dest_url} seems to be invalid");
+        }
+        
+        let mut srcset = String::new();
+        let mut avif_srcset = String::new();
+        let mut dimensions = None;
+        let mut placeholder = None;
+        
+        if path.exists() && transformer::is_image(&path) {
+            let info = match self.known_images.get(Path::new(dest_url)) {
+                Some(info) => info.clone(),
+                None => transformer::inspect(&path, self.options)?,
+            };
+            let (width, height) = info.dimensions;
+            
+            if !options.passthrough && !info.animated {
+                let full_size = transformer::transform_filename(dest_url, &options);
+                
+                for (variant, variant_width) in transformer::responsive_variants(dest_url, width) {
+                    srcset.push_str(&format!("{} {variant_width}w, ", variant.display()));
+                    
+                    if options.avif {
+                        avif_srcset.push_str(&format!("{} {variant_width}w, ", variant.with_extension("avif").display()));
+                    }
+                }
+                
+                if !srcset.is_empty() {
+                    srcset.push_str(&format!("{} {width}w", full_size.display()));
+                }
+                
+                if options.avif {
+                    avif_srcset.push_str(&format!("{} {width}w", full_size.with_extension("avif").display()));
+                }
+            }
+            
+            dimensions = Some((width, height));
+            placeholder = info.placeholder.clone();
+            self.image_info.insert(PathBuf::from(dest_url), info);
+        } else if path.exists() && transformer::is_svg(&path) {
+            dimensions = svg::dimensions(&std::fs::read_to_string(&path)?);
+        }
+        
+        let url = if path.exists() {
+            transformer::transform_filename(dest_url, &options).to_str().unwrap().to_string()
+        } else {
+            dest_url.to_string()
+        };
+        
+        Ok(Image {
+            url,
+            alt,
+            srcset,
+            avif_srcset,
+            dimensions,
+            placeholder,
+            lazy,
+        })
+    }
+    
+    /// Renders a <figure> block whose images become the subfigures (a), (b), ... of a single figure
+    fn render_figure_group(&mut self, parser: &mut md::Parser, output: &mut String) -> Result<()> {
+        let mut subfigures = Vec::new();
+        
+        loop {
+            match parser.next() {
+                Some(md::Event::Html(tag)) if tag.trim() == "</figure>" => break,
+                Some(md::Event::Start(md::Tag::Image { dest_url, title, .. })) => {
+                    if subfigures.len() == MAX_SUBFIGURES {
+                        anyhow::bail!("A <figure> can hold at most {MAX_SUBFIGURES} images");
+                    }
+                    
+                    let alt = strip_tags(&self.collect(parser)?);
+                    let alt = self.alt_text(&dest_url, &[&alt, &escape_html(&title), &strip_tags(&self.description)])?;
+                    
+                    let image = self.render_image(dest_url.as_ref(), alt, self.figure_cursor > 1 || !subfigures.is_empty())?;
+                    self.text.push_str(title.as_ref());
+                    self.text.push(' ');
+                    
+                    subfigures.push(Subfigure {
+                        letter: (b'a' + subfigures.len() as u8) as char,
+                        caption: title.to_string(),
+                        image,
+                    });
+                },
+                Some(md::Event::Start(md::Tag::Paragraph | md::Tag::HtmlBlock)) |
+                Some(md::Event::End(md::TagEnd::Paragraph | md::TagEnd::HtmlBlock)) |
+                Some(md::Event::SoftBreak) => {},
+                Some(md::Event::Text(text)) if text.trim().is_empty() => {},
+                Some(_) => anyhow::bail!("A <figure> may only contain images and must be separated from them by blank lines"),
+                None => anyhow::bail!("Missing </figure>"),
+            }
+        }
+        
+        if subfigures.is_empty() {
+            anyhow::bail!("Empty <figure>");
+        }
+        
+        append_template(output, FigureGroup {
+            number: self.figure_cursor,
+            subfigures: &subfigures,
+            description: &self.description,
+        })?;
+        self.figure_cursor += 1;
+        self.description.clear();
+        self.figure_attributes = None;
+        
+        Ok(())
+    }
+    
     fn collect(&mut self, parser: &mut md::Parser) -> Result<String> {
         let mut temp = String::with_capacity(4 * 1024);
         
         while let Some(event) = parser.next() {
             match event {
                 md::Event::End(_) => {
+                    self.text.push(' ');
                     break;
                 },
                 event => self.dispatch(event, parser, &mut temp)?,
@@ -395,9 +889,9 @@ impl<'a> Renderer<'a> {
         while let Some(event) = parser.next() {
             match &event {
                 md::Event::InlineHtml(tag) |
-                md::Event::Html(tag) => if tag.as_ref() == end_tag {
+                md::Event::Html(tag) if tag.as_ref() == end_tag => {
                     break;
-                }
+                },
                 _ => {},
             }
             
@@ -466,7 +960,7 @@ impl<'a> Renderer<'a> {
             anyhow::bail!("Bibliography is not last element in post");
         }
         
-        bib.sort_by(|a, b| a.0.cmp(&b.0));
+        bib.sort_by_key(|a| a.0);
         
         /* Check that all citations have an entry in the bibliography */
         for (name, id) in &self.references {
@@ -479,6 +973,8 @@ impl<'a> Renderer<'a> {
             }
         }
         
+        self.citations = bib.iter().map(|(_, data)| unescape_html(&strip_tags(data))).collect();
+        
         /* Generate html */
         append_template(output, Bibliography {
             references: &bib,
@@ -487,51 +983,112 @@ impl<'a> Renderer<'a> {
         Ok(())
     }
     
-    pub fn render_footer(&self) -> Result<String> {
+    pub fn render_footer(&self, post: &Post, citation: &cite::Citation) -> Result<String> {
         let mut output = String::with_capacity(1024);
-        append_template(&mut output, PostFooter {})?;
+        append_template(&mut output, PostFooter {
+            bibtex: citation.bibtex(),
+            bibtex_url: cite::bibtex_filename(post.url()).to_string_lossy().into_owned(),
+            csl_url: cite::csl_filename(post.url()).to_string_lossy().into_owned(),
+            series: self.context.series.as_ref(),
+            previous: self.context.previous.as_ref(),
+            next: self.context.next.as_ref(),
+            related: &self.context.related,
+            backlinks: &self.context.backlinks,
+        })?;
         Ok(output)
     }
 }
 
-pub fn render_index(entries: &[&CacheEntry]) -> Result<String> {
+pub fn render_index(entries: &[&CacheEntry], assets: &AssetManifest) -> Result<String> {
     let mut output = String::with_capacity(4096);
     append_template(&mut output, Index {
         entries,
+        assets,
     })?;
     Ok(output)
 }
 
-pub fn render_archive(entries: &[&CacheEntry]) -> Result<String> {
+pub fn render_archive(entries: &[&CacheEntry], assets: &AssetManifest) -> Result<String> {
     let mut output = String::with_capacity(4096);
     append_template(&mut output, Archive {
         entries,
+        assets,
     })?;
     Ok(output)
 }
 
-pub fn render_404() -> Result<String> {
+/// Renders the landing page of every series and returns their urls together with the pages
+pub fn render_series(entries: &[&CacheEntry], assets: &AssetManifest) -> Result<Vec<(String, String)>> {
+    let mut series: BTreeMap<String, Vec<&CacheEntry>> = BTreeMap::new();
+    
+    for entry in entries {
+        if let Some(s) = entry.metadata().series() {
+            series.entry(s.url()).or_default().push(entry);
+        }
+    }
+    
+    let mut pages = Vec::with_capacity(series.len());
+    
+    for (url, mut parts) in series {
+        parts.sort_by_key(|entry| entry.metadata().series().map(|s| s.part()));
+        let mut output = String::with_capacity(4096);
+        
+        append_template(&mut output, SeriesPage {
+            name: parts[0].metadata().series().unwrap().name(),
+            entries: &parts,
+            assets,
+        })?;
+        
+        pages.push((url, output));
+    }
+    
+    Ok(pages)
+}
+
+pub fn render_404(assets: &AssetManifest) -> Result<String> {
     let mut output = String::with_capacity(4096);
-    append_template(&mut output, Status404 {})?;
+    append_template(&mut output, Status404 {
+        assets,
+    })?;
     Ok(output)
 }
 
+/// The date of the most recent publication or update
 fn max_post_date(entries: &[&CacheEntry]) -> PostDate {
     let mut ret = PostDate::default();
     
     for post in entries {
-        if post.metadata().date() > &ret {
-            ret.clone_from(post.metadata().date());
+        if post.metadata().updated() > &ret {
+            ret.clone_from(post.metadata().updated());
         }
     }
     
     ret
 }
 
+fn iso_date(date: &PostDate) -> String {
+    format!("{:04}-{:02}-{:02}", date.year(), date.month(), date.day())
+}
+
 fn atom_timestamp(date: &PostDate) -> String {
     format!("{:04}-{:02}-{:02}T00:00:00Z", date.year(), date.month(), date.day())
 }
 
+fn rss_timestamp(date: &PostDate) -> Result<String> {
+    let Some(date) = chrono::NaiveDate::from_ymd_opt(date.year() as i32, date.month() as u32, date.day() as u32) else {
+        anyhow::bail!("Invalid date: {:02}.{:02}.{:04}", date.day(), date.month(), date.year());
+    };
+    Ok(date.format("%a, %d %b %Y 00:00:00 +0000").to_string())
+}
+
+fn absolute_url(url: &str) -> String {
+    if url.starts_with('/') {
+        format!("https://z2-2z.github.io{url}")
+    } else {
+        url.to_string()
+    }
+}
+
 pub fn render_feed(entries: &[&CacheEntry]) -> Result<String> {
     let mut output = String::with_capacity(4096);
     
@@ -546,15 +1103,13 @@ pub fn render_feed(entries: &[&CacheEntry]) -> Result<String> {
     
     for entry in entries {
         let published = atom_timestamp(entry.metadata().date());
-        let url = if entry.url().starts_with('/') {
-            format!("https://z2-2z.github.io{}", entry.url())
-        } else {
-            entry.url().to_string()
-        };
+        let updated = atom_timestamp(entry.metadata().updated());
         let entry = AtomEntry {
             title: entry.metadata().title(),
-            url,
+            summary: entry.summary(),
+            url: absolute_url(entry.url()),
             published,
+            updated,
             categories: entry.metadata().categories(),
         };
         elements.push(entry);
@@ -568,6 +1123,121 @@ pub fn render_feed(entries: &[&CacheEntry]) -> Result<String> {
     Ok(output)
 }
 
+pub fn render_rss(entries: &[&CacheEntry]) -> Result<String> {
+    let mut output = String::with_capacity(4096);
+    
+    /* Without posts the channel stays valid but has no build date */
+    let latest_date = max_post_date(entries);
+    let updated = if latest_date.year() == 0 {
+        None
+    } else {
+        Some(rss_timestamp(&latest_date)?)
+    };
+    let mut items = Vec::new();
+    
+    for entry in entries {
+        let published = rss_timestamp(entry.metadata().date())?;
+        let item = RssItem {
+            title: entry.metadata().title(),
+            summary: entry.summary(),
+            url: absolute_url(entry.url()),
+            published,
+            categories: entry.metadata().categories(),
+        };
+        items.push(item);
+    }
+    
+    append_template(&mut output, RssFeed {
+        updated: updated.as_deref(),
+        items: &items,
+    })?;
+    
+    Ok(output)
+}
+
+pub fn render_json_feed(entries: &[&CacheEntry]) -> Result<String> {
+    let mut output = String::with_capacity(4096);
+    let mut items = Vec::new();
+    
+    for entry in entries {
+        let published = atom_timestamp(entry.metadata().date());
+        let updated = atom_timestamp(entry.metadata().updated());
+        let item = JsonFeedItem {
+            title: entry.metadata().title(),
+            summary: entry.summary(),
+            text: entry.text(),
+            url: absolute_url(entry.url()),
+            published,
+            updated,
+            categories: entry.metadata().categories(),
+        };
+        items.push(item);
+    }
+    
+    append_template(&mut output, JsonFeed {
+        items: &items,
+    })?;
+    
+    Ok(output)
+}
+
+pub fn render_sitemap(entries: &[&CacheEntry]) -> Result<String> {
+    let mut output = String::with_capacity(4096);
+    let updated = iso_date(&max_post_date(entries));
+    
+    /* Mirrored posts are hosted elsewhere */
+    let mut pages: Vec<(String, String)> = entries.iter()
+        .filter(|entry| entry.url().starts_with('/'))
+        .map(|entry| (absolute_url(entry.url()), iso_date(entry.metadata().updated())))
+        .collect();
+    
+    /* A series landing page changes with its most recent part */
+    let mut series: BTreeMap<String, &PostDate> = BTreeMap::new();
+    
+    for entry in entries {
+        if let Some(s) = entry.metadata().series() {
+            let updated = series.entry(absolute_url(&s.url())).or_insert(entry.metadata().updated());
+            *updated = (*updated).max(entry.metadata().updated());
+        }
+    }
+    
+    pages.extend(series.into_iter().map(|(url, updated)| (url, iso_date(updated))));
+    
+    append_template(&mut output, Sitemap {
+        updated: &updated,
+        pages: &pages,
+    })?;
+    
+    Ok(output)
+}
+
+/// The default site and settings that tests render posts with
+#[cfg(test)]
+#[derive(Default)]
+pub struct RenderFixture {
+    pub assets: AssetManifest,
+    pub options: TransformOptions,
+    pub site: Site,
+    pub context: PostContext,
+    pub known_images: HashMap<PathBuf, ImageInfo>,
+}
+
+#[cfg(test)]
+impl RenderFixture {
+    pub fn renderer<'a>(&'a self, basedir: &'a Path, offline: bool) -> Renderer<'a> {
+        Renderer::new(RenderParams {
+            basedir,
+            offline,
+            strict: false,
+            assets: &self.assets,
+            options: &self.options,
+            site: &self.site,
+            context: &self.context,
+            known_images: &self.known_images,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -575,9 +1245,157 @@ mod tests {
     #[test]
     fn render_example() {
         let post = crate::posts::Post::new("test-data/renderer/example.md", false).unwrap();
-        let mut renderer = Renderer::new(Path::new("test-data/renderer/"), false);
+        let fixture = RenderFixture::default();
+        let mut renderer = fixture.renderer(Path::new("test-data/renderer/"), false);
         let output = renderer.render_body(post.content()).unwrap();
         println!("{output}");
         println!("{renderer:?}");
     }
+    
+    #[test]
+    fn test_json_ld() {
+        let post = crate::posts::Post::new("test-data/renderer/example.md", true).unwrap();
+        let fixture = RenderFixture::default();
+        let mut renderer = fixture.renderer(Path::new("test-data/renderer/"), true);
+        renderer.render_body(post.content()).unwrap();
+        let header = renderer.render_header(&post).unwrap();
+        let start = header.find("<script type=\"application/ld+json\">").unwrap();
+        let json = &header[start..];
+        let json = &json[json.find('>').unwrap() + 1..json.find("</script>").unwrap()];
+        let json: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(json["@type"], "ScholarlyArticle");
+        assert_eq!(json["citation"][1], "or just some text");
+        assert_eq!(json["headline"], post.metadata().title());
+    }
+    
+    #[test]
+    fn test_feeds() {
+        let cache = crate::posts::PostCache::render(&["test-data/series/one.md", "test-data/series/two.md"]);
+        let mut entries: Vec<&CacheEntry> = cache.resources().collect();
+        entries.sort_by(|a, b| b.metadata().date().cmp(a.metadata().date()));
+        
+        let rss = render_rss(&entries).unwrap();
+        let rss = roxmltree::Document::parse(&rss).unwrap();
+        let items: Vec<_> = rss.descendants().filter(|n| n.has_tag_name("item")).collect();
+        assert_eq!(items.len(), 2);
+        let child = |node: &roxmltree::Node, name: &str| node.children().find(|n| n.has_tag_name(name)).and_then(|n| n.text()).map(str::to_owned);
+        assert_eq!(child(&items[0], "title").unwrap(), "Triaging Crashes");
+        assert_eq!(child(&items[0], "link").unwrap(), "https://z2-2z.github.io/2024/feb/08/triaging-crashes.html");
+        assert_eq!(child(&items[0], "pubDate").unwrap(), "Thu, 08 Feb 2024 00:00:00 +0000");
+        assert_eq!(child(&items[1], "category").unwrap(), "fuzzing");
+        
+        let atom = render_feed(&entries).unwrap();
+        assert_eq!(roxmltree::Document::parse(&atom).unwrap().descendants().filter(|n| n.has_tag_name("entry")).count(), 2);
+        
+        let json: serde_json::Value = serde_json::from_str(&render_json_feed(&entries).unwrap()).unwrap();
+        assert_eq!(json["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(json["items"].as_array().unwrap().len(), 2);
+        assert_eq!(json["items"][1]["title"], "Building a Harness");
+        assert_eq!(json["items"][1]["date_published"], "2024-02-01T00:00:00Z");
+        assert_eq!(json["items"][1]["tags"][0], "fuzzing");
+        assert_ne!(json["items"][1]["content_text"], json["items"][1]["title"]);
+        assert!(json["items"][1]["content_text"].as_str().unwrap().contains("The harness."));
+    }
+    
+    #[test]
+    fn test_empty_rss() {
+        let rss = render_rss(&[]).unwrap();
+        let rss = roxmltree::Document::parse(&rss).unwrap();
+        assert!(rss.descendants().any(|n| n.has_tag_name("channel")));
+        assert!(!rss.descendants().any(|n| n.has_tag_name("item") || n.has_tag_name("lastBuildDate")));
+    }
+    
+    #[test]
+    fn test_post_links() {
+        let mut fixture = RenderFixture::default();
+        
+        for path in ["test-data/series/one.md", "test-data/series/two.md"] {
+            let post = crate::posts::Post::new(path, true).unwrap();
+            fixture.site.add(Path::new(path), post.metadata(), post.url()).unwrap();
+        }
+        
+        let site = &fixture.site;
+        let mut renderer = fixture.renderer(Path::new("test-data/series/"), false);
+        let output = renderer.render_body(b"See [part one](one.md#setup) and [[Triaging Crashes]].").unwrap();
+        assert!(output.contains("href=\"/2024/feb/01/building-a-harness.html#setup\""));
+        assert!(output.contains("href=\"/2024/feb/08/triaging-crashes.html\""));
+        assert_eq!(renderer.links().len(), 2);
+        assert_eq!(linked_posts(b"[[Building a Harness]] and [missing](three.md)", Path::new("test-data/series/"), site), [PathBuf::from("test-data/series/one.md")]);
+        
+        let mut renderer = fixture.renderer(Path::new("test-data/series/"), false);
+        assert!(renderer.render_body(b"[missing](three.md)").is_err());
+        assert!(renderer.render_body(b"[[Unknown Title]]").is_err());
+    }
+    
+    #[test]
+    fn test_json_ld_minified() {
+        let post = crate::posts::Post::new("test-data/renderer/example.md", true).unwrap();
+        let fixture = RenderFixture::default();
+        let mut renderer = fixture.renderer(Path::new("test-data/renderer/"), true);
+        let body = renderer.render_body(post.content()).unwrap();
+        let citation = cite::Citation::new(&post, None).unwrap();
+        let mut page = format!("{}{body}{}", renderer.render_header(&post).unwrap(), renderer.render_footer(&post, &citation).unwrap()).into_bytes();
+        let dir = crate::fs::TestDir::new("json-ld");
+        let output = dir.join("post.html");
+        transformer::transform_buffer(&mut page, &output, &fixture.options).unwrap();
+        let html = std::fs::read_to_string(&output).unwrap();
+        let start = html.find("application/ld+json").unwrap();
+        let json = &html[start..];
+        let json = &json[json.find('>').unwrap() + 1..json.find("</script>").unwrap()];
+        let json: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(json["headline"], post.metadata().title());
+        assert_eq!(json["citation"][1], "or just some text");
+        assert!(!html.contains("\n                \"@context\""));
+    }
+    
+    #[test]
+    fn test_strip_tags() {
+        assert_eq!(strip_tags("A <i>fuzzing</i>\n  setup &amp; <span class=\"x\">more</span>"), "A fuzzing setup &amp; more");
+    }
+    
+    #[test]
+    fn test_unescape_html() {
+        assert_eq!(unescape_html("a &#38; b &#60;c&#62; &#34;q&#34; &#x27;x&#39; &amp;lt; & &unknown;"), "a & b <c> \"q\" 'x' &lt; & &unknown;");
+        
+        let fixture = RenderFixture::default();
+        let mut renderer = fixture.renderer(Path::new("test-data/renderer/"), true);
+        renderer.render_body(b"Fuzzing & triage: `a < b` and \"quotes\" aren't escaped twice.").unwrap();
+        assert_eq!(renderer.summary, "Fuzzing & triage: a < b and \"quotes\" aren't escaped twice.");
+    }
+    
+    #[test]
+    fn test_summarize() {
+        let text = "word ".repeat(100);
+        let summary = summarize(text.trim());
+        assert!(summary.chars().count() <= SUMMARY_LEN + 1);
+        assert!(summary.ends_with("word…"));
+        assert_eq!(summarize("short"), "short");
+    }
+    
+    #[test]
+    fn test_known_images() {
+        let mut fixture = RenderFixture::default();
+        let mut renderer = fixture.renderer(Path::new("test-data/renderer/"), true);
+        renderer.render_body(b"![mini](./mini.jpg)").unwrap();
+        let info = renderer.image_info()[Path::new("./mini.jpg")].clone();
+        assert!(!info.animated);
+        
+        /* Unchanged images are not read again */
+        fixture.known_images.insert(PathBuf::from("./mini.jpg"), ImageInfo {
+            dimensions: (7, 3),
+            ..info
+        });
+        let mut renderer = fixture.renderer(Path::new("test-data/renderer/"), true);
+        let output = renderer.render_body(b"![mini](./mini.jpg)").unwrap();
+        assert!(output.contains("width=\"7\" height=\"3\""));
+    }
+    
+    #[test]
+    fn test_figure_attributes() {
+        let mut options = ImageOptions::default();
+        parse_figure_attributes(r#"<figure-title quality="60" lossless avif>"#, &mut options).unwrap();
+        assert_eq!(options.quality, 60.0);
+        assert!(options.lossless && options.avif && !options.passthrough);
+        assert!(parse_figure_attributes(r#"<figure-title quality="200">"#, &mut options).is_err());
+    }
 }
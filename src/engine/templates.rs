@@ -1,4 +1,4 @@
-use crate::posts::CacheEntry;
+use crate::{posts::{CacheEntry, PostChange, PostLink, SeriesContext}, assets::AssetManifest};
 use std::collections::HashSet;
 use askama::Template;
 
@@ -9,12 +9,30 @@ pub struct PostHeader<'a> {
     pub uses_code: bool,
     pub languages: &'a HashSet<String>,
     pub keywords: String,
-    pub url: &'a str,
+    pub categories: &'a [String],
+    pub url: String,
+    pub published: String,
+    pub updated: String,
+    pub unlisted: bool,
+    pub article_type: &'a str,
+    pub citations: &'a [String],
+    pub summary: &'a str,
+    pub og_image: String,
+    pub assets: &'a AssetManifest,
 }
 
 #[derive(Template)]
 #[template(path = "post/footer.html")]
-pub struct PostFooter;
+pub struct PostFooter<'a> {
+    pub bibtex: &'a str,
+    pub bibtex_url: String,
+    pub csl_url: String,
+    pub series: Option<&'a SeriesContext>,
+    pub previous: Option<&'a PostLink>,
+    pub next: Option<&'a PostLink>,
+    pub related: &'a [PostLink],
+    pub backlinks: &'a [PostLink],
+}
 
 #[derive(Template)]
 #[template(path = "post/headline.html")]
@@ -129,15 +147,40 @@ pub struct Link<'a> {
     pub content: &'a str,
 }
 
+/// A rendered image as it is included by post/image.html
+pub struct Image {
+    pub url: String,
+    pub alt: String,
+    pub srcset: String,
+    pub avif_srcset: String,
+    pub dimensions: Option<(u32, u32)>,
+    pub placeholder: Option<String>,
+    pub lazy: bool,
+}
+
 #[derive(Template)]
 #[template(path = "post/figure.html")]
 pub struct Figure<'a> {
     pub number: usize,
-    pub url: &'a str,
+    pub image: &'a Image,
     pub description: &'a str,
     pub inside_p: bool,
 }
 
+pub struct Subfigure {
+    pub letter: char,
+    pub caption: String,
+    pub image: Image,
+}
+
+#[derive(Template)]
+#[template(path = "post/figure_group.html")]
+pub struct FigureGroup<'a> {
+    pub number: usize,
+    pub subfigures: &'a [Subfigure],
+    pub description: &'a str,
+}
+
 #[derive(Template)]
 #[template(path = "post/linebreak.html")]
 pub struct Linebreak;
@@ -165,30 +208,71 @@ pub struct Categories<'a> {
     pub day: u8,
     pub month: &'a str,
     pub year: u16,
+    pub reading_time: usize,
+    pub updated: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "post/series.html")]
+pub struct SeriesBox<'a> {
+    pub series: &'a SeriesContext,
+}
+
+#[derive(Template)]
+#[template(path = "post/changelog.html")]
+pub struct Changelog<'a> {
+    pub changes: &'a [&'a PostChange],
+}
+
+#[derive(Template)]
+#[template(path = "post/toc.html")]
+pub struct TableOfContents<'a> {
+    pub headings: &'a [(String, String)],
 }
 
 #[derive(Template)]
 #[template(path = "index.html")]
 pub struct Index<'a> {
     pub entries: &'a [&'a CacheEntry],
+    pub assets: &'a AssetManifest,
+}
+
+#[derive(Template)]
+#[template(path = "series.html")]
+pub struct SeriesPage<'a> {
+    pub name: &'a str,
+    pub entries: &'a [&'a CacheEntry],
+    pub assets: &'a AssetManifest,
+}
+
+#[derive(Template)]
+#[template(path = "sitemap.xml")]
+pub struct Sitemap<'a> {
+    pub updated: &'a str,
+    pub pages: &'a [(String, String)],
 }
 
 #[derive(Template)]
 #[template(path = "404.html")]
-pub struct Status404;
+pub struct Status404<'a> {
+    pub assets: &'a AssetManifest,
+}
 
 #[derive(Template)]
 #[template(path = "archive.html")]
 pub struct Archive<'a> {
     pub entries: &'a [&'a CacheEntry],
+    pub assets: &'a AssetManifest,
 }
 
 #[derive(Template)]
 #[template(path = "feed/atom_entry.xml")]
 pub struct AtomEntry<'a> {
     pub title: &'a str,
+    pub summary: &'a str,
     pub url: String,
     pub published: String,
+    pub updated: String,
     pub categories: &'a [String],
 }
 
@@ -198,3 +282,38 @@ pub struct AtomFeed<'a> {
     pub updated: &'a str,
     pub entries: &'a [AtomEntry<'a>],
 }
+
+#[derive(Template)]
+#[template(path = "feed/rss_item.xml")]
+pub struct RssItem<'a> {
+    pub title: &'a str,
+    pub summary: &'a str,
+    pub url: String,
+    pub published: String,
+    pub categories: &'a [String],
+}
+
+#[derive(Template)]
+#[template(path = "feed/rss.xml")]
+pub struct RssFeed<'a> {
+    pub updated: Option<&'a str>,
+    pub items: &'a [RssItem<'a>],
+}
+
+#[derive(Template)]
+#[template(path = "feed/json_item.json", escape = "none")]
+pub struct JsonFeedItem<'a> {
+    pub title: &'a str,
+    pub summary: &'a str,
+    pub text: &'a str,
+    pub url: String,
+    pub published: String,
+    pub updated: String,
+    pub categories: &'a [String],
+}
+
+#[derive(Template)]
+#[template(path = "feed/feed.json", escape = "none")]
+pub struct JsonFeed<'a> {
+    pub items: &'a [JsonFeedItem<'a>],
+}
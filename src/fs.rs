@@ -1,4 +1,5 @@
 use std::path::{Path, PathBuf};
+use std::collections::HashMap;
 use std::sync::mpsc::{channel, Receiver};
 use std::fs::{File, read_dir, create_dir};
 use std::time::Duration;
@@ -6,7 +7,7 @@ use memmap2::Mmap;
 use anyhow::Result;
 use notify::Watcher;
 
-use crate::transformer;
+use crate::{transformer, assets::AssetManifest};
 
 pub fn mmap_file<P: AsRef<Path>>(path: P) -> Result<Mmap> {
     let file = File::open(path)?;
@@ -22,33 +23,101 @@ pub fn is_newer<P1: AsRef<Path>, P2: AsRef<Path>>(src: P1, dst: P2) -> bool {
     src.metadata().unwrap().modified().unwrap() > dst.metadata().unwrap().modified().unwrap()
 }
 
-fn cpr_helper(force: bool, current_dir: &Path, root: &Path, output: &Path) -> Result<()> {
+/// Removes older fingerprinted versions of the static files in a folder.
+/// `current` maps the name of each file without fingerprint to its current fingerprinted name.
+fn remove_old_fingerprints(dir: &Path, current: &HashMap<String, String>) -> Result<()> {
+    if current.is_empty() {
+        return Ok(());
+    }
+    
+    let mut stale = Vec::new();
+    
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|x| x.to_str()) else {
+            continue;
+        };
+        let plain = transformer::strip_fingerprint(name).unwrap_or_else(|| name.to_owned());
+        
+        if current.get(&plain).is_some_and(|current_name| current_name != name) {
+            stale.push(path);
+        }
+    }
+    
+    for path in stale {
+        transformer::remove_output(&path)?;
+    }
+    
+    Ok(())
+}
+
+fn cpr_helper(force: bool, current_dir: &Path, root: &Path, output: &Path, options: &transformer::TransformOptions, assets: &mut AssetManifest) -> Result<()> {
+    let mut fingerprinted = HashMap::new();
+    
     for entry in read_dir(current_dir)? {
         let src_path = entry?.path();
-        let dst_path = output.join(src_path.strip_prefix(root)?);
-        let trans_path = transformer::transform_filename(&dst_path);
+        let mut dst_path = output.join(src_path.strip_prefix(root)?);
         
         if src_path.is_dir() {
             if !dst_path.exists() {
                 create_dir(&dst_path)?;
             }
-            cpr_helper(force, &src_path, root, output)?;
-        } else if force || is_newer(&src_path, &trans_path) {
-            transformer::transform_file(src_path, dst_path)?;
+            cpr_helper(force, &src_path, root, output, options, assets)?;
+            continue;
+        }
+        
+        let options = &transformer::TransformOptions {
+            image: options.image.for_file(&src_path),
+            ..options.clone()
+        };
+        let mut content = None;
+        
+        if transformer::is_fingerprinted(&src_path) {
+            let buffer = std::fs::read(&src_path)?;
+            let url = transformer::transform_filename(&dst_path, &options.image);
+            dst_path = transformer::fingerprint_filename(&dst_path, &transformer::fingerprint(&buffer));
+            let fingerprinted = transformer::transform_filename(&dst_path, &options.image);
+            
+            assets.insert(
+                format!("/{}", url.strip_prefix(output)?.display()),
+                format!("/{}", fingerprinted.strip_prefix(output)?.display()),
+            );
+            content = Some(buffer);
+        }
+        
+        let trans_path = transformer::transform_filename(&dst_path, &options.image);
+        
+        if force || is_newer(&src_path, &trans_path) || !transformer::is_precompressed(&trans_path, options) {
+            match content {
+                Some(mut buffer) => transformer::transform_buffer(&mut buffer, &dst_path, options)?,
+                None => transformer::transform_file(&src_path, &dst_path, options)?,
+            }
+        }
+        
+        if transformer::is_fingerprinted(&src_path) {
+            let name = trans_path.file_name().unwrap().to_str().unwrap();
+            
+            if let Some(plain) = transformer::strip_fingerprint(name) {
+                fingerprinted.insert(plain, name.to_owned());
+            }
         }
     }
     
-    Ok(())
+    remove_old_fingerprints(&output.join(current_dir.strip_prefix(root)?), &fingerprinted)
 }
 
-pub fn copy_dir_recursive<P1: AsRef<Path>, P2: AsRef<Path>>(force: bool, input: P1, output: P2) -> Result<()> {
+/// Transforms all static files into the output folder and returns
+/// the urls of the fingerprinted files
+pub fn copy_dir_recursive<P1: AsRef<Path>, P2: AsRef<Path>>(force: bool, input: P1, output: P2, options: &transformer::TransformOptions) -> Result<AssetManifest> {
     let output = output.as_ref();
+    let mut assets = AssetManifest::default();
     
     if !output.exists() {
         create_dir(output)?;
     }
     
-    cpr_helper(force, input.as_ref(), input.as_ref(), output)
+    cpr_helper(force, input.as_ref(), input.as_ref(), output, options, &mut assets)?;
+    Ok(assets)
 }
 
 pub struct FileWatcher {
@@ -100,10 +169,64 @@ impl FileWatcher {
     }
 }
 
+/// A fresh output folder for a test that is removed again when dropped.
+/// The process id keeps parallel test runs apart.
+#[cfg(test)]
+pub struct TestDir(PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("dove-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDir {
+    type Target = Path;
+    
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     
+    #[test]
+    fn test_remove_old_fingerprints() {
+        let dir = TestDir::new("fingerprints");
+        
+        for name in ["app.js", "app.abc123.js", "app.abc123.js.gz", "app.def456.js", "other.js", "style.111111.css"] {
+            std::fs::write(dir.join(name), []).unwrap();
+        }
+        
+        let current = HashMap::from([("app.js".to_owned(), "app.def456.js".to_owned())]);
+        remove_old_fingerprints(&dir, &current).unwrap();
+        
+        let mut names: Vec<String> = read_dir(&*dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        assert_eq!(names, ["app.def456.js", "other.js", "style.111111.css"]);
+    }
+    
     #[test]
     fn test_dir_watcher() {
         let mut watcher = FileWatcher::new("test-data/watcher").unwrap();
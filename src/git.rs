@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::posts::{PostChange, PostDate};
+
+/// The commits that touched the files of a folder. The log is read
+/// with a single git invocation and only read again when HEAD moved.
+/// Folders outside of a git repository have no history.
+#[derive(Default)]
+pub struct GitHistory {
+    dir: PathBuf,
+    head: Option<String>,
+    files: HashMap<PathBuf, Vec<PostChange>>,
+}
+
+impl GitHistory {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        let mut history = Self {
+            dir: dir.as_ref().to_owned(),
+            ..Default::default()
+        };
+        history.refresh();
+        history
+    }
+    
+    /// Reads the log again if a commit was made since the last read
+    pub fn refresh(&mut self) {
+        let head = git(&self.dir, &["rev-parse", "HEAD"]);
+        
+        if head.is_some() && head == self.head {
+            return;
+        }
+        
+        self.files = match git(&self.dir, &["-c", "core.quotepath=off", "log", "--relative", "--name-status", "-M", "--format=%x00%as%x09%s"]) {
+            Some(log) => parse_log(&log)
+                .into_iter()
+                .map(|(path, changes)| (self.dir.join(path), changes))
+                .collect(),
+            None => HashMap::new(),
+        };
+        self.head = head;
+    }
+    
+    /// Returns the commits that touched a file, newest first.
+    /// Renames are followed like with `git log --follow`.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Vec<PostChange> {
+        self.files.get(path.as_ref()).cloned().unwrap_or_default()
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output();
+    
+    match output {
+        Ok(output) if output.status.success() => Some(String::from_utf8_lossy(&output.stdout).into_owned()),
+        _ => None,
+    }
+}
+
+/// Groups the commits of a log by the current name of the files they touched
+fn parse_log(log: &str) -> HashMap<String, Vec<PostChange>> {
+    let mut files: HashMap<String, Vec<PostChange>> = HashMap::new();
+    
+    /* Older names of renamed files map to their current name */
+    let mut renames: HashMap<String, String> = HashMap::new();
+    
+    for commit in log.split('\0') {
+        let mut lines = commit.lines();
+        let Some((date, message)) = lines.next().and_then(|line| line.split_once('\t')) else {
+            continue;
+        };
+        let Some(date) = PostDate::from_iso(date) else {
+            continue;
+        };
+        
+        for line in lines {
+            let mut fields = line.split('\t');
+            let (Some(status), Some(path)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            
+            let current = if status.starts_with('R') {
+                let Some(new) = fields.next() else {
+                    continue;
+                };
+                let current = renames.get(new).cloned().unwrap_or_else(|| new.to_owned());
+                renames.insert(path.to_owned(), current.clone());
+                current
+            } else {
+                renames.get(path).cloned().unwrap_or_else(|| path.to_owned())
+            };
+            
+            files.entry(current).or_default().push(PostChange {
+                date: date.clone(),
+                message: message.trim().to_owned(),
+            });
+        }
+    }
+    
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_parse_log() {
+        let log = "\x002024-03-05\tFix typo in the harness\n\nM\tposts/harness.md\nA\tposts/other.md\n\x00garbage\n\x002024-02-01\tRename\n\nR090\tdrafts/harness.md\tposts/harness.md\n\x002024-01-01\tInitial version\n\nA\tdrafts/harness.md\n";
+        let files = parse_log(log);
+        let history = &files["posts/harness.md"];
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].date, PostDate::from_iso("2024-03-05").unwrap());
+        assert_eq!(history[0].message, "Fix typo in the harness");
+        assert_eq!(history[2].message, "Initial version");
+        assert_eq!(files["posts/other.md"].len(), 1);
+        assert!(!files.contains_key("drafts/harness.md"));
+    }
+}
@@ -1,13 +1,22 @@
 use std::path::{PathBuf, Path};
+use std::collections::{BTreeMap, HashMap};
 use anyhow::Result;
 use clap::Parser;
 
 mod engine;
+mod assets;
 mod posts;
 mod fs;
 mod transformer;
 mod parser;
 mod net;
+mod search;
+mod weight;
+mod svg;
+mod metadata;
+mod og;
+mod cite;
+mod git;
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
@@ -19,161 +28,389 @@ struct Args {
     command: Commands,
 }
 
+#[derive(clap::Args)]
+struct RenderArgs {
+    #[arg(short, long, required = true, value_name = "DIR")]
+    input: String,
+    
+    #[arg(short, long, required = true, value_name = "DIR")]
+    output: String,
+    
+    #[arg(short, long, required = true, value_name = "FILE")]
+    cache: String,
+    
+    #[arg(short, long)]
+    force: bool,
+    
+    #[arg(long)]
+    offline: bool,
+    
+    #[arg(short, long)]
+    live: bool,
+    
+    #[arg(long, default_value_t = String::from("./static"))]
+    static_folder: String,
+    
+    #[arg(long, value_name = "FILE")]
+    weight_report: Option<String>,
+    
+    #[arg(long, value_name = "KB")]
+    page_budget: Option<u64>,
+    
+    #[arg(long)]
+    compressed_weight: bool,
+    
+    #[arg(long)]
+    precompress: bool,
+    
+    #[arg(long, default_value_t = 85.0, value_name = "0-100")]
+    image_quality: f32,
+    
+    #[arg(long)]
+    lossless_png: bool,
+    
+    #[arg(long)]
+    avif: bool,
+    
+    #[arg(long, value_name = "DIR")]
+    image_cache: Option<String>,
+    
+    #[arg(long)]
+    strict: bool,
+    
+    #[arg(long, value_name = "FILE")]
+    og_background: Option<String>,
+    
+    #[arg(long)]
+    git_dates: bool,
+    
+    #[arg(long, value_name = "DD.MM.YYYY")]
+    now: Option<String>,
+    
+    #[arg(long, default_value_t = 3, value_name = "N")]
+    related_posts: usize,
+}
+
 #[derive(clap::Subcommand)]
 enum Commands {
-    Render {
-        #[arg(short, long, required = true, value_name = "DIR")]
-        input: String,
-        
-        #[arg(short, long, required = true, value_name = "DIR")]
-        output: String,
-        
-        #[arg(short, long, required = true, value_name = "FILE")]
-        cache: String,
-        
-        #[arg(short, long)]
-        force: bool,
-        
-        #[arg(short, long)]
-        offline: bool,
-        
-        #[arg(short, long)]
-        live: bool,
-        
-        #[arg(long, default_value_t = String::from("./static"))]
-        static_folder: String,
-    },
+    Render(Box<RenderArgs>),
     
     New {
         output: String,
     },
+    
+    Stats {
+        #[arg(short, long, required = true, value_name = "FILE")]
+        cache: String,
+    },
 }
 
-#[allow(clippy::too_many_arguments)]
-fn render(input_dir: &str, output_dir: &str, cache_file: &str, force: bool, live: bool, offline: bool, static_folder: &str, watcher: &mut fs::FileWatcher) -> Result<()> {
-    /* Copy static files */
-    fs::copy_dir_recursive(
-        force,
-        static_folder,
-        output_dir,
-    )?;
+fn render(args: &RenderArgs, force: bool, watcher: &mut fs::FileWatcher) -> Result<()> {
+    let input_dir = args.input.as_str();
+    let output_dir = args.output.as_str();
+    let cache_file = args.cache.as_str();
+    let static_folder = args.static_folder.as_str();
+    let live = args.live;
+    let offline = args.offline;
+    let options = transformer::TransformOptions {
+        precompress: args.precompress,
+        image: transformer::ImageOptions {
+            quality: args.image_quality,
+            lossless_png: args.lossless_png,
+            avif: args.avif,
+            ..Default::default()
+        },
+        image_cache: Some(match &args.image_cache {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(format!("{cache_file}.images")),
+        }),
+    };
     
-    let out_404 = PathBuf::from(format!("{output_dir}/404.html"));
-    if force || !out_404.exists() {
-        let mut output = engine::render_404()?.into_bytes();
-        transformer::transform_buffer(&mut output, out_404)?;
+    /* Posts are scheduled relative to this date */
+    let now = match &args.now {
+        Some(date) => posts::PostDate::parse(date)?,
+        None => posts::PostDate::today(),
+    };
+    
+    if !(0.0..=100.0).contains(&options.image.quality) {
+        anyhow::bail!("The image quality must be between 0 and 100");
     }
     
+    let og_background = match &args.og_background {
+        Some(path) => Some(std::fs::read(path)?),
+        None => None,
+    };
+    
+    /* Cached images that a full build does not use anymore are removed afterwards */
+    let build_start = std::time::SystemTime::now();
+    
     /* Read posts */
     let mut cache = posts::PostCache::new(cache_file)?;
     let mut cache_changed = false;
     
-    if force {
+    /* All images must be encoded again when the site-wide image settings changed */
+    let images_changed = cache.image_options() != &options.image;
+    
+    /* Copy static files */
+    let assets = fs::copy_dir_recursive(
+        force || images_changed,
+        static_folder,
+        output_dir,
+        &options,
+    )?;
+    
+    /* Pages must be rerendered when the fingerprint of a static file changed */
+    let assets_changed = cache.assets() != &assets;
+    
+    if force || assets_changed || images_changed {
         cache.clear();
+        cache.set_assets(assets.clone());
+        cache.set_image_options(options.image.clone());
+        cache_changed = true;
     }
     
+    /* Only a build that encoded all images knows which cache entries are still needed */
+    let mut prune_images = force || images_changed;
+    
+    let out_404 = PathBuf::from(format!("{output_dir}/404.html"));
+    if force || assets_changed || !out_404.exists() || !transformer::is_precompressed(&out_404, &options) {
+        let mut output = engine::render_404(&assets)?.into_bytes();
+        transformer::transform_buffer(&mut output, out_404, &options)?;
+    }
+    
+    /* The git log is read once and only again when a commit was made */
+    let mut git_history = if args.git_dates { Some(git::GitHistory::new(input_dir)) } else { None };
+    
     loop {
-        for input_file in posts::PostIterator::new(input_dir)? {
-            let rerender = if let Some(entry) = cache.get(&input_file) {
-                (!live && entry.metadata().draft()) || entry.dependencies().iter().any(|d| fs::is_newer(d.input(), d.output()))
+        let input_files: Vec<PathBuf> = posts::PostIterator::new(input_dir)?.collect();
+        
+        if let Some(git_history) = &mut git_history {
+            git_history.refresh();
+        }
+        
+        /* Forget posts whose source was deleted */
+        cache_changed |= cache.prune(&input_files)?;
+        
+        /* Parse all posts that changed since the last build */
+        let mut changed = HashMap::new();
+        
+        for input_file in &input_files {
+            /* Commits don't touch the post so the history must be compared too */
+            let history = git_history.as_ref().map(|git_history| git_history.get(input_file)).unwrap_or_default();
+            let rerender = if let Some(entry) = cache.get(input_file) {
+                (!live && !entry.metadata().published(&now)) || entry.metadata().history() != history.as_slice() || entry.dependencies().iter().any(|d| fs::is_newer(d.input(), d.output()) || !transformer::is_precompressed(d.output(), &options))
             } else {
                 true
             };
             
             if rerender {
-                let mut input_basedir = input_file.clone();
-                input_basedir.pop();
-                let mut output_basedir;
-                let post = posts::Post::new(&input_file, offline)?;
-                let mut renderer = engine::Renderer::new(&input_basedir, offline);
-                let html_path;
+                let mut post = posts::Post::new(input_file, offline)?;
+                post.metadata_mut().set_history(history);
+                changed.insert(input_file.clone(), post);
+            }
+        }
+        
+        /* Posts link to each other, so collect the metadata of all visible posts first */
+        let mut site = posts::Site::new(args.related_posts);
+        
+        for input_file in &input_files {
+            let (metadata, url) = match (changed.get(input_file), cache.get(input_file)) {
+                (Some(post), _) => (post.metadata(), post.url()),
+                (None, Some(entry)) => (entry.metadata(), entry.url()),
+                (None, None) => continue,
+            };
+            
+            if live || metadata.published(&now) {
+                site.add(input_file, metadata, url)?;
+            }
+        }
+        
+        /* Backlinks need the links of all posts. Posts that did not change still link to the same files. */
+        for input_file in &input_files {
+            let links = match (changed.get(input_file), cache.get(input_file)) {
+                (Some(post), _) => engine::linked_posts(post.content(), input_file.parent().unwrap_or(Path::new("")), &site),
+                (None, Some(entry)) => entry.links().iter().map(|(input, _)| input.clone()).collect(),
+                (None, None) => continue,
+            };
+            site.set_links(input_file, &links);
+        }
+        
+        for input_file in &input_files {
+            let context = site.context(input_file);
+            let post = match changed.remove(input_file) {
+                Some(post) => post,
+                None => match cache.get(input_file) {
+                    /* Posts that did not change are rendered again when their context or the url of a linked post changed */
+                    Some(entry) if entry.context() != &context || site.links_changed(entry.links()) => {
+                        let mut post = posts::Post::new(input_file, offline)?;
+                        post.metadata_mut().set_history(entry.metadata().history().to_vec());
+                        post
+                    },
+                    _ => continue,
+                },
+            };
+            
+            let mut input_basedir = input_file.clone();
+            input_basedir.pop();
+            let known_images = cache.get(input_file).map(|entry| entry.known_images(&input_basedir)).unwrap_or_default();
+            let mut output_basedir;
+            let mut renderer = engine::Renderer::new(engine::RenderParams {
+                basedir: &input_basedir,
+                offline,
+                strict: args.strict,
+                assets: &assets,
+                options: &options,
+                site: &site,
+                context: &context,
+                known_images: &known_images,
+            });
+            let html_path;
+            
+            if !post.metadata().published(&now) && !live {
+                cache_changed |= cache.delete(input_file)?;
+                continue;
+            }
+            
+            if live {
+                println!("[{}] Rendering {}...", chrono::Local::now().format("%H:%M:%S"), input_file.display());
+            } else {
+                println!("Rendering {}...", input_file.display());
+            }
+            
+            if let Some(filename) = post.filename() {
+                let mut body = renderer.render_body(post.content())?.into_bytes();
                 
-                if post.metadata().draft() && !live {
-                    cache_changed |= cache.delete(&input_file);
-                    continue;
+                /* Check languages  */
+                for lang in renderer.languages_used() {
+                    let path = format!("{static_folder}/js/hljs/{lang}.min.js");
+                    let path = Path::new(&path);
+                    
+                    if !path.exists() {
+                        anyhow::bail!("Language {lang} does not exist");
+                    }
                 }
                 
-                if live {
-                    println!("[{}] Rendering {}...", chrono::Local::now().format("%H:%M:%S"), input_file.display());
-                } else {
-                    println!("Rendering {}...", input_file.display());
+                let mut header = renderer.render_header(&post)?.into_bytes();
+                let citation = cite::Citation::new(&post, args.now.as_ref().map(|_| &now))?;
+                let mut footer = renderer.render_footer(&post, &citation)?.into_bytes();
+                
+                /* Render page */
+                let output_file = format!("{output_dir}/{filename}");
+                output_basedir = PathBuf::from(&output_file);
+                output_basedir.pop();
+                if !output_basedir.exists() {
+                    std::fs::create_dir_all(&output_basedir)?;
                 }
                 
-                if let Some(filename) = post.filename() {
-                    let mut body = renderer.render_body(post.content())?.into_bytes();
-                    
-                    /* Check languages  */
-                    for lang in renderer.languages_used() {
-                        let path = format!("{static_folder}/js/hljs/{lang}.min.js");
-                        let path = Path::new(&path);
-                        
-                        if !path.exists() {
-                            anyhow::bail!("Language {lang} does not exist");
-                        }
-                    }
-                    
-                    let mut header = renderer.render_header(&post)?.into_bytes();
-                    let mut footer = renderer.render_footer()?.into_bytes();
-                    
-                    /* Render page */
-                    let output_file = format!("{output_dir}/{filename}");
-                    output_basedir = PathBuf::from(&output_file);
-                    output_basedir.pop();
-                    if !output_basedir.exists() {
-                        std::fs::create_dir_all(&output_basedir)?;
-                    }
-                    
-                    //TODO: optimize this
-                    header.reserve(body.len() + footer.len());
-                    header.append(&mut body);
-                    header.append(&mut footer);
-                    
-                    transformer::transform_buffer(&mut header, &output_file)?;
-                    
-                    /* Copy file mentions */
-                    for path in renderer.file_mentions() {
-                        transformer::transform_file(
-                            input_basedir.join(path),
-                            output_basedir.join(path)
-                        )?;
-                    }
-                    
-                    html_path = output_file;
-                } else {
-                    html_path = format!("{output_dir}/archive.html");
-                    output_basedir = PathBuf::from(&output_dir);
+                //TODO: optimize this
+                header.reserve(body.len() + footer.len());
+                header.append(&mut body);
+                header.append(&mut footer);
+                
+                transformer::transform_buffer(&mut header, &output_file, &options)?;
+                
+                /* Render social preview image */
+                let og_image = og::render(post.metadata(), og_background.as_deref(), &options)?;
+                transformer::write_output(&og::filename(&output_file), &og_image, &options)?;
+                
+                /* Write citation files */
+                transformer::write_output(&cite::bibtex_filename(&output_file), citation.bibtex().as_bytes(), &options)?;
+                transformer::write_output(&cite::csl_filename(&output_file), citation.csl_json().as_bytes(), &options)?;
+                
+                /* Copy file mentions */
+                for path in renderer.file_mentions() {
+                    let options = transformer::TransformOptions {
+                        image: renderer.image_options(path),
+                        ..options.clone()
+                    };
+                    transformer::transform_file(
+                        input_basedir.join(path),
+                        output_basedir.join(path),
+                        &options,
+                    )?;
                 }
                 
-                cache_changed |= cache.insert(
-                    &input_basedir,
-                    &input_file,
-                    &output_basedir,
-                    Path::new(&html_path),
-                    &post,
-                    &renderer,
-                );
+                html_path = output_file;
+            } else {
+                html_path = format!("{output_dir}/archive.html");
+                output_basedir = PathBuf::from(&output_dir);
             }
+            
+            cache_changed |= cache.insert(
+                &input_basedir,
+                input_file,
+                &output_basedir,
+                Path::new(&html_path),
+                &post,
+                &renderer,
+            )?;
         }
         
-        if cache_changed {
-            let mut entries: Vec<&posts::CacheEntry> = cache.resources().collect();
+        if cache_changed || !transformer::is_precompressed(format!("{output_dir}/index.html"), &options) {
+            /* Unlisted posts are only reachable by their url */
+            let mut entries: Vec<&posts::CacheEntry> = cache.resources().filter(|entry| !entry.metadata().unlisted()).collect();
             entries.sort_by(|a, b| b.metadata().date().cmp(a.metadata().date()));
             
             /* Render index */
-            let mut output = engine::render_index(&entries)?.into_bytes();
-            transformer::transform_buffer(&mut output, format!("{output_dir}/index.html"))?;
+            let mut output = engine::render_index(&entries, &assets)?.into_bytes();
+            transformer::transform_buffer(&mut output, format!("{output_dir}/index.html"), &options)?;
             
             /* Render archive */
-            let mut output = engine::render_archive(&entries)?.into_bytes();
-            transformer::transform_buffer(&mut output, format!("{output_dir}/archive.html"))?;
+            let mut output = engine::render_archive(&entries, &assets)?.into_bytes();
+            transformer::transform_buffer(&mut output, format!("{output_dir}/archive.html"), &options)?;
             
             /* Render feed */
             let mut output = engine::render_feed(&entries)?.into_bytes();
-            transformer::transform_buffer(&mut output, format!("{output_dir}/atom.xml"))?;
+            transformer::transform_buffer(&mut output, format!("{output_dir}/atom.xml"), &options)?;
+            
+            let mut output = engine::render_rss(&entries)?.into_bytes();
+            transformer::transform_buffer(&mut output, format!("{output_dir}/rss.xml"), &options)?;
+            
+            let mut output = engine::render_json_feed(&entries)?.into_bytes();
+            transformer::transform_buffer(&mut output, format!("{output_dir}/feed.json"), &options)?;
+            
+            /* Render sitemap */
+            let mut output = engine::render_sitemap(&entries)?.into_bytes();
+            transformer::transform_buffer(&mut output, format!("{output_dir}/sitemap.xml"), &options)?;
+            
+            /* Render series landing pages */
+            let series_dir = PathBuf::from(format!("{output_dir}/series"));
+            
+            if series_dir.exists() {
+                std::fs::remove_dir_all(&series_dir)?;
+            }
+            
+            for (url, page) in engine::render_series(&entries, &assets)? {
+                std::fs::create_dir_all(&series_dir)?;
+                let mut output = page.into_bytes();
+                transformer::transform_buffer(&mut output, format!("{output_dir}{url}"), &options)?;
+            }
+            
+            /* Build search index */
+            search::write_index(&entries, output_dir, &options)?;
             
             cache.save(cache_file)?;
         }
         
+        if prune_images {
+            transformer::prune_image_cache(&options, build_start)?;
+            prune_images = false;
+        }
+        
+        /* Measure page weights */
+        if args.weight_report.is_some() || args.page_budget.is_some() {
+            let pages = weight::html_pages(output_dir)?;
+            let weights = weight::measure_pages(output_dir, &pages, args.compressed_weight)?;
+            
+            if let Some(report) = &args.weight_report {
+                weight::write_report(report, &weights)?;
+            }
+            
+            if let Some(budget) = args.page_budget {
+                weight::check_budget(&weights, budget * 1024)?;
+            }
+        }
+        
         if live {
             watcher.wait()?;
         } else {
@@ -190,20 +427,84 @@ fn new(output: String) -> Result<()> {
     Ok(())
 }
 
+fn stats(cache_file: &str) -> Result<()> {
+    let cache = posts::PostCache::new(cache_file)?;
+    let mut entries: Vec<&posts::CacheEntry> = cache.resources().collect();
+    entries.sort_by(|a, b| b.metadata().date().cmp(a.metadata().date()));
+    
+    let mut total = posts::PostStats::default();
+    let mut years: BTreeMap<u16, usize> = BTreeMap::new();
+    let mut categories: BTreeMap<&str, usize> = BTreeMap::new();
+    
+    println!("{:<10}  {:>6}  {:>4}  {:>5}  {:>4}  {:>4}  {:>4}  Title", "Date", "Words", "Read", "Code", "Fig", "Tab", "Cite");
+    
+    for entry in &entries {
+        let date = entry.metadata().date();
+        let post_stats = entry.stats();
+        
+        println!(
+            "{:02}.{:02}.{:04}  {:>6}  {:>3}m  {:>5}  {:>4}  {:>4}  {:>4}  {}",
+            date.day(), date.month(), date.year(),
+            post_stats.words,
+            post_stats.reading_time(),
+            post_stats.code_lines,
+            post_stats.figures,
+            post_stats.tables,
+            post_stats.citations,
+            entry.metadata().title(),
+        );
+        
+        total += post_stats;
+        *years.entry(date.year()).or_default() += 1;
+        
+        for category in entry.metadata().categories() {
+            *categories.entry(category).or_default() += 1;
+        }
+    }
+    
+    println!();
+    println!("Posts:      {}", entries.len());
+    println!("Words:      {}", total.words);
+    println!("Code lines: {}", total.code_lines);
+    println!("Figures:    {}", total.figures);
+    println!("Tables:     {}", total.tables);
+    println!("Citations:  {}", total.citations);
+    
+    println!();
+    println!("Posts per year:");
+    for (year, count) in years.iter().rev() {
+        println!("  {year:04}: {count}");
+    }
+    
+    let mut categories: Vec<(&str, usize)> = categories.into_iter().collect();
+    categories.sort_by_key(|c| std::cmp::Reverse(c.1));
+    
+    println!();
+    println!("Posts per category:");
+    for (category, count) in categories {
+        println!("  {category}: {count}");
+    }
+    
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
     
     match args.command {
-        Commands::Render { input, output, cache, mut force, live, offline, static_folder } => {
-            let mut watcher = fs::FileWatcher::new(&input)?;
+        Commands::Render(render_args) => {
+            let mut watcher = fs::FileWatcher::new(&render_args.input)?;
+            let mut force = render_args.force;
             
             loop {
-                match render(&input, &output, &cache, force, live, offline, &static_folder, &mut watcher) {
+                /* Only live mode keeps going so that --strict and --page-budget can fail a build */
+                match render(&render_args, force, &mut watcher) {
                     Ok(_) => break,
-                    Err(error) => println!("ERROR: {error}"),
+                    Err(error) if render_args.live => println!("ERROR: {error}"),
+                    Err(error) => return Err(error),
                 }
                 
-                if live {
+                if render_args.live {
                     watcher.wait()?;
                     force = false;
                 } else {
@@ -214,5 +515,54 @@ fn main() -> Result<()> {
             Ok(())
         },
         Commands::New { output } => new(output),
+        Commands::Stats { cache } => stats(&cache),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Renders a folder of posts with an empty static folder and the given extra arguments
+    fn render_test(dir: &Path, input: &Path, extra: &[&str]) -> Result<()> {
+        let static_folder = dir.join("static");
+        std::fs::create_dir_all(&static_folder).unwrap();
+        
+        let mut args = vec![
+            "dove".to_owned(), "render".to_owned(), "--offline".to_owned(),
+            "--input".to_owned(), input.display().to_string(),
+            "--output".to_owned(), dir.join("output").display().to_string(),
+            "--cache".to_owned(), dir.join("cache").display().to_string(),
+            "--static-folder".to_owned(), static_folder.display().to_string(),
+        ];
+        args.extend(extra.iter().map(|arg| arg.to_string()));
+        
+        let Commands::Render(render_args) = Args::parse_from(args).command else {
+            unreachable!();
+        };
+        let mut watcher = fs::FileWatcher::new(input)?;
+        render(&render_args, true, &mut watcher)
+    }
+    
+    #[test]
+    fn test_page_budget_fails_build() {
+        let dir = fs::TestDir::new("page-budget");
+        let error = render_test(&dir, Path::new("test-data/series"), &["--page-budget", "1"]).unwrap_err();
+        assert!(error.to_string().contains("exceeds the budget"));
+        
+        assert!(render_test(&dir, Path::new("test-data/series"), &["--page-budget", "100000"]).is_ok());
+    }
+    
+    #[test]
+    fn test_strict_fails_build() {
+        let dir = fs::TestDir::new("strict");
+        let input = dir.join("posts");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::write(input.join("post.md"), "date: 01.02.2024\ncategories: test\n\n# Missing Alt Text\n\n![](missing.jpg)\n").unwrap();
+        
+        assert!(render_test(&dir, &input, &[]).is_ok());
+        
+        let error = render_test(&dir, &input, &["--strict"]).unwrap_err();
+        assert!(error.to_string().contains("has neither alt text nor a description"));
     }
 }
@@ -0,0 +1,381 @@
+use std::io::Cursor;
+use anyhow::Result;
+use image::{ImageFormat, ImageReader};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// PNG chunks that carry text, EXIF data or timestamps
+const PNG_METADATA_CHUNKS: [&[u8]; 5] = [b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"tIME"];
+
+/// GIF application extensions that are needed for playback
+const GIF_ANIMATION_EXTENSIONS: [&[u8]; 2] = [b"NETSCAPE2.0", b"ANIMEXTS1.0"];
+
+/// WebP chunks that carry EXIF data or XMP packets
+const WEBP_METADATA_CHUNKS: [&[u8]; 2] = [b"EXIF", b"XMP "];
+
+/// Flags of the VP8X chunk that announce EXIF data and an XMP packet
+const WEBP_METADATA_FLAGS: u8 = 0x08 | 0x04;
+
+#[inline]
+fn contains(data: &[u8], needle: &[u8]) -> bool {
+    data.windows(needle.len()).any(|window| window == needle)
+}
+
+/// Returns the kinds of sensitive metadata that an image carries in
+/// its EXIF data, XMP packet or text chunks
+pub fn sensitive_metadata(data: &[u8]) -> Vec<&'static str> {
+    let mut location = contains(data, b"exif:GPSLatitude");
+    let mut author = contains(data, b"dc:creator") || contains(data, b"tEXtAuthor\0");
+    
+    if let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(data)) {
+        location |= exif.fields().any(|field| field.tag.context() == exif::Context::Gps);
+        author |= exif.fields().any(|field| matches!(field.tag, exif::Tag::Artist | exif::Tag::CameraOwnerName));
+    }
+    
+    let mut kinds = Vec::new();
+    
+    if location {
+        kinds.push("location");
+    }
+    
+    if author {
+        kinds.push("author");
+    }
+    
+    kinds
+}
+
+fn strip_jpeg(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut pos = 2;
+    
+    output.extend_from_slice(&data[..2]);
+    
+    while pos < data.len() {
+        if data[pos] != 0xFF || pos + 1 >= data.len() {
+            anyhow::bail!("Invalid jpeg segment");
+        }
+        
+        let marker = data[pos + 1];
+        
+        match marker {
+            /* End of image */
+            0xD9 => {
+                output.extend_from_slice(&data[pos..pos + 2]);
+                break;
+            },
+            /* Fill byte */
+            0xFF => {
+                pos += 1;
+                continue;
+            },
+            /* Markers without payload */
+            0x01 | 0xD0..=0xD8 => {
+                output.extend_from_slice(&data[pos..pos + 2]);
+                pos += 2;
+                continue;
+            },
+            _ => {},
+        }
+        
+        if pos + 4 > data.len() {
+            anyhow::bail!("Truncated jpeg segment");
+        }
+        
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let end = pos + 2 + len;
+        
+        if end > data.len() {
+            anyhow::bail!("Truncated jpeg segment");
+        }
+        
+        /* Keep JFIF (APP0), ICC profiles (APP2) and the Adobe color transform (APP14) */
+        let is_metadata = matches!(marker, 0xE1 | 0xE3..=0xED | 0xEF | 0xFE);
+        
+        if !is_metadata {
+            output.extend_from_slice(&data[pos..end]);
+        }
+        
+        pos = end;
+        
+        /* Start of scan: the entropy-coded data runs until the next marker. Progressive
+           images have several scans with tables and possibly metadata in between. */
+        if marker == 0xDA {
+            let scan_end = jpeg_scan_end(data, pos);
+            output.extend_from_slice(&data[pos..scan_end]);
+            pos = scan_end;
+        }
+    }
+    
+    Ok(output)
+}
+
+/// Returns the position of the first marker after the entropy-coded data at `pos`.
+/// Stuffed zero bytes and restart markers are part of the data.
+fn jpeg_scan_end(data: &[u8], mut pos: usize) -> usize {
+    while pos + 1 < data.len() {
+        if data[pos] == 0xFF && !matches!(data[pos + 1], 0x00 | 0xD0..=0xD7) {
+            return pos;
+        }
+        
+        pos += 1;
+    }
+    
+    data.len()
+}
+
+fn strip_png(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut pos = PNG_SIGNATURE.len();
+    
+    output.extend_from_slice(PNG_SIGNATURE);
+    
+    while pos < data.len() {
+        if pos + 12 > data.len() {
+            anyhow::bail!("Truncated png chunk");
+        }
+        
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into()?) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let end = pos + 12 + len;
+        
+        if end > data.len() {
+            anyhow::bail!("Truncated png chunk");
+        }
+        
+        if !PNG_METADATA_CHUNKS.contains(&kind) {
+            output.extend_from_slice(&data[pos..end]);
+        }
+        
+        pos = end;
+    }
+    
+    Ok(output)
+}
+
+/// Returns the end of the data sub-blocks starting at `pos`
+fn skip_gif_sub_blocks(data: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        match data.get(pos) {
+            Some(0) => return Ok(pos + 1),
+            Some(len) => pos += 1 + *len as usize,
+            None => anyhow::bail!("Truncated gif data"),
+        }
+    }
+}
+
+fn strip_gif(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut pos = 13;
+    
+    if data.len() < pos {
+        anyhow::bail!("Truncated gif header");
+    }
+    
+    /* Global color table */
+    if data[10] & 0x80 != 0 {
+        pos += 3 << ((data[10] & 0x07) + 1);
+    }
+    
+    output.extend_from_slice(&data[..pos.min(data.len())]);
+    
+    while let Some(&block) = data.get(pos) {
+        match block {
+            /* Extension */
+            0x21 => {
+                let label = data.get(pos + 1).copied().unwrap_or(0);
+                let end = skip_gif_sub_blocks(data, pos + 2)?;
+                let identifier = data.get(pos + 3..pos + 14).unwrap_or(&[]);
+                let is_metadata = match label {
+                    0xFE => true,
+                    0xFF => !GIF_ANIMATION_EXTENSIONS.contains(&identifier),
+                    _ => false,
+                };
+                
+                if !is_metadata {
+                    output.extend_from_slice(&data[pos..end.min(data.len())]);
+                }
+                
+                pos = end;
+            },
+            /* Image descriptor */
+            0x2C => {
+                let flags = data.get(pos + 9).copied().unwrap_or(0);
+                let mut start = pos + 10;
+                
+                if flags & 0x80 != 0 {
+                    start += 3 << ((flags & 0x07) + 1);
+                }
+                
+                let end = skip_gif_sub_blocks(data, start + 1)?;
+                output.extend_from_slice(&data[pos..end.min(data.len())]);
+                pos = end;
+            },
+            /* Trailer */
+            0x3B => {
+                output.push(block);
+                break;
+            },
+            _ => anyhow::bail!("Invalid gif block"),
+        }
+    }
+    
+    Ok(output)
+}
+
+fn strip_webp(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut pos = 12;
+    
+    output.extend_from_slice(&data[..pos]);
+    
+    while pos < data.len() {
+        if pos + 8 > data.len() {
+            anyhow::bail!("Truncated webp chunk");
+        }
+        
+        let kind = &data[pos..pos + 4];
+        let len = u32::from_le_bytes(data[pos + 4..pos + 8].try_into()?) as usize;
+        /* Chunks are padded to an even size */
+        let end = (pos + 8 + len + (len & 1)).min(data.len());
+        
+        if pos + 8 + len > data.len() {
+            anyhow::bail!("Truncated webp chunk");
+        }
+        
+        if !WEBP_METADATA_CHUNKS.contains(&kind) {
+            let start = output.len();
+            output.extend_from_slice(&data[pos..end]);
+            
+            if kind == b"VP8X" && len > 0 {
+                output[start + 8] &= !WEBP_METADATA_FLAGS;
+            }
+        }
+        
+        pos = end;
+    }
+    
+    let riff_len = (output.len() - 8) as u32;
+    output[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    Ok(output)
+}
+
+/// TIFF, BMP and ICO files keep their metadata in places that cannot simply be cut out.
+/// Decoding and encoding them losslessly leaves only the pixels.
+fn reencode(data: &[u8], format: ImageFormat) -> Result<Vec<u8>> {
+    let image = ImageReader::with_format(Cursor::new(data), format).decode()?;
+    let mut output = Vec::new();
+    image.write_to(&mut Cursor::new(&mut output), format)?;
+    Ok(output)
+}
+
+/// AVIF files keep their metadata in boxes that cannot be removed without rewriting the file
+pub fn is_avif(data: &[u8]) -> bool {
+    data.len() >= 12 && &data[4..8] == b"ftyp" && matches!(&data[8..12], b"avif" | b"avis")
+}
+
+/// Removes EXIF data, XMP packets, comments and text chunks from
+/// an image without touching its pixel data. Images whose metadata
+/// cannot be removed are refused.
+pub fn strip(data: &[u8]) -> Result<Vec<u8>> {
+    if data.starts_with(b"\xFF\xD8") {
+        strip_jpeg(data)
+    } else if data.starts_with(PNG_SIGNATURE) {
+        strip_png(data)
+    } else if data.starts_with(b"GIF8") {
+        strip_gif(data)
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        strip_webp(data)
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        reencode(data, ImageFormat::Tiff)
+    } else if data.starts_with(b"BM") {
+        reencode(data, ImageFormat::Bmp)
+    } else if data.starts_with(b"\0\0\x01\0") {
+        reencode(data, ImageFormat::Ico)
+    } else if is_avif(data) {
+        anyhow::bail!("The metadata of AVIF images cannot be removed. Publish the image as PNG or JPEG instead.")
+    } else {
+        anyhow::bail!("Unknown image format, its metadata cannot be removed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_strip_jpeg() {
+        let jpeg = b"\xFF\xD8\xFF\xE0\x00\x04JF\xFF\xE1\x00\x06Exif\xFF\xFE\x00\x03x\xFF\xDA\x00\x02\x12\x34\xFF\xD9";
+        assert_eq!(strip(jpeg).unwrap(), b"\xFF\xD8\xFF\xE0\x00\x04JF\xFF\xDA\x00\x02\x12\x34\xFF\xD9");
+    }
+    
+    #[test]
+    fn test_strip_progressive_jpeg() {
+        /* Metadata between two scans, with stuffed bytes and a restart marker in the scan data */
+        let jpeg = b"\xFF\xD8\xFF\xDA\x00\x02\x12\xFF\x00\xFF\xD0\x34\xFF\xE1\x00\x06Exif\xFF\xFE\x00\x03x\xFF\xC4\x00\x03\x01\xFF\xDA\x00\x02\x56\xFF\xD9";
+        assert_eq!(strip(jpeg).unwrap(), b"\xFF\xD8\xFF\xDA\x00\x02\x12\xFF\x00\xFF\xD0\x34\xFF\xC4\x00\x03\x01\xFF\xDA\x00\x02\x56\xFF\xD9");
+    }
+    
+    /// A png chunk with a valid length but without a valid crc, which strip does not check
+    fn png_chunk(kind: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut chunk = (payload.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(payload);
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+    
+    #[test]
+    fn test_strip_png() {
+        let header = png_chunk(b"IHDR", &[0; 13]);
+        let data = png_chunk(b"IDAT", b"pixels");
+        let end = png_chunk(b"IEND", b"");
+        let mut png = PNG_SIGNATURE.to_vec();
+        
+        for chunk in [&header, &png_chunk(b"tEXt", b"Author\0z2"), &png_chunk(b"eXIf", b"MM\0*"), &data, &png_chunk(b"iTXt", b"XML:com.adobe.xmp\0\0\0\0\0<x/>"), &end] {
+            png.extend_from_slice(chunk);
+        }
+        
+        assert_eq!(sensitive_metadata(&png), ["author"]);
+        assert_eq!(strip(&png).unwrap(), [PNG_SIGNATURE, &header, &data, &end].concat());
+    }
+    
+    #[test]
+    fn test_strip_gif() {
+        let header = b"GIF89a\x01\x00\x01\x00\x00\x00\x00";
+        let netscape = b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00";
+        let comment = b"\x21\xFE\x05hello\x00";
+        let xmp = b"\x21\xFF\x0BXMP DataXMP\x04<x/>\x00";
+        let image = b"\x2C\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02\x44\x01\x00";
+        let gif = [&header[..], netscape, comment, xmp, image, b"\x3B"].concat();
+        assert_eq!(strip(&gif).unwrap(), [&header[..], netscape, image, b"\x3B"].concat());
+    }
+    
+    #[test]
+    fn test_strip_webp() {
+        let vp8x = b"VP8X\x0A\x00\x00\x00\x0C\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        let exif = b"EXIF\x05\x00\x00\x00MM\0*\0\0";
+        let image = b"VP8L\x02\x00\x00\x00\x2F\x00";
+        let mut webp = b"RIFF\0\0\0\0WEBP".to_vec();
+        webp.extend_from_slice(vp8x);
+        webp.extend_from_slice(exif);
+        webp.extend_from_slice(image);
+        
+        let stripped = strip(&webp).unwrap();
+        assert_eq!(&stripped[20], &0x00);
+        assert_eq!(&stripped[30..], image);
+        assert_eq!(u32::from_le_bytes(stripped[4..8].try_into().unwrap()) as usize, stripped.len() - 8);
+    }
+    
+    #[test]
+    fn test_strip_reencoded() {
+        let image = image::RgbImage::from_pixel(2, 2, image::Rgb([1, 2, 3]));
+        let mut tiff = Vec::new();
+        image.write_to(&mut Cursor::new(&mut tiff), ImageFormat::Tiff).unwrap();
+        
+        let stripped = strip(&tiff).unwrap();
+        let decoded = ImageReader::with_format(Cursor::new(&stripped), ImageFormat::Tiff).decode().unwrap();
+        assert_eq!(decoded.to_rgb8(), image);
+        assert!(strip(b"\0\0\0\x1CftypavifAAAA").is_err());
+    }
+}
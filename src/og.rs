@@ -0,0 +1,203 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont, point};
+use image::{ImageFormat, ImageReader, Rgba, RgbaImage, imageops::FilterType};
+
+use crate::transformer::{self, TransformOptions};
+use crate::posts::PostMetadata;
+
+const WIDTH: u32 = 1200;
+const HEIGHT: u32 = 630;
+const MARGIN: f32 = 80.0;
+const TITLE_SIZE: f32 = 68.0;
+const TITLE_LINES: usize = 4;
+const META_SIZE: f32 = 30.0;
+
+/* Colors of common.css */
+const BACKGROUND: Rgba<u8> = Rgba([0x11, 0x11, 0x11, 0xFF]);
+const FOREGROUND: Rgba<u8> = Rgba([0xF5, 0xF5, 0xF5, 0xFF]);
+const LINK: Rgba<u8> = Rgba([0x90, 0xDF, 0x94, 0xFF]);
+const CITE: Rgba<u8> = Rgba([0x92, 0x93, 0x8A, 0xFF]);
+
+/// How much a custom background is darkened to keep the text readable
+const BACKGROUND_DIM: f32 = 0.7;
+
+static FONT: &[u8] = include_bytes!("../resources/DejaVuSans-Bold.ttf");
+
+/// The social preview image of a page is stored next to it
+pub fn filename<P: AsRef<Path>>(page: P) -> PathBuf {
+    page.as_ref().with_extension("og.png")
+}
+
+fn text_width<F: ScaleFont<G>, G: Font>(font: &F, text: &str) -> f32 {
+    let mut width = 0.0;
+    let mut previous = None;
+    
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    
+    width
+}
+
+/// Greedily breaks text into at most `max_lines` lines that fit into `max_width`
+fn wrap<F: ScaleFont<G>, G: Font>(font: &F, text: &str, max_width: f32, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{line} {word}")
+        };
+        
+        if line.is_empty() || text_width(font, &candidate) <= max_width {
+            line = candidate;
+        } else {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        }
+    }
+    
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        let last = lines.last_mut().unwrap();
+        
+        while !last.is_empty() && text_width(font, &format!("{last}…")) > max_width {
+            last.pop();
+        }
+        
+        last.push('…');
+    }
+    
+    lines
+}
+
+fn blend(pixel: &mut Rgba<u8>, color: Rgba<u8>, alpha: f32) {
+    for i in 0..3 {
+        pixel[i] = (pixel[i] as f32 * (1.0 - alpha) + color[i] as f32 * alpha).round() as u8;
+    }
+}
+
+fn draw_text<F: Font>(canvas: &mut RgbaImage, font: &F, size: f32, x: f32, baseline: f32, text: &str, color: Rgba<u8>) {
+    let scale = PxScale::from(size);
+    let scaled = font.as_scaled(scale);
+    let mut cursor = x;
+    let mut previous = None;
+    
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        
+        if let Some(previous) = previous {
+            cursor += scaled.kern(previous, id);
+        }
+        
+        let glyph = id.with_scale_and_position(scale, point(cursor, baseline));
+        
+        if let Some(outline) = font.outline_glyph(glyph) {
+            let bounds = outline.px_bounds();
+            
+            outline.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i32 + gx as i32;
+                let py = bounds.min.y as i32 + gy as i32;
+                
+                if px >= 0 && py >= 0 && (px as u32) < canvas.width() && (py as u32) < canvas.height() {
+                    blend(canvas.get_pixel_mut(px as u32, py as u32), color, coverage);
+                }
+            });
+        }
+        
+        cursor += scaled.h_advance(id);
+        previous = Some(id);
+    }
+}
+
+fn draw(title: &str, subtitle: &str, background: Option<&[u8]>) -> Result<Vec<u8>> {
+    let font = FontRef::try_from_slice(FONT)?;
+    
+    let mut canvas = match background {
+        Some(data) => {
+            let image = ImageReader::new(Cursor::new(data)).with_guessed_format()?.decode()?;
+            let mut canvas = image.resize_to_fill(WIDTH, HEIGHT, FilterType::Lanczos3).to_rgba8();
+            
+            for pixel in canvas.pixels_mut() {
+                blend(pixel, BACKGROUND, BACKGROUND_DIM);
+            }
+            
+            canvas
+        },
+        None => RgbaImage::from_pixel(WIDTH, HEIGHT, BACKGROUND),
+    };
+    
+    let max_width = WIDTH as f32 - 2.0 * MARGIN;
+    let title_font = font.as_scaled(PxScale::from(TITLE_SIZE));
+    let lines = wrap(&title_font, title, max_width, TITLE_LINES);
+    let line_height = TITLE_SIZE * 1.2;
+    
+    /* Center the title vertically between the header and the footer */
+    let mut baseline = (HEIGHT as f32 - lines.len() as f32 * line_height) / 2.0 + title_font.ascent();
+    
+    draw_text(&mut canvas, &font, META_SIZE, MARGIN, MARGIN, "z2's blog", LINK);
+    
+    for line in &lines {
+        draw_text(&mut canvas, &font, TITLE_SIZE, MARGIN, baseline, line, FOREGROUND);
+        baseline += line_height;
+    }
+    
+    let meta_font = font.as_scaled(PxScale::from(META_SIZE));
+    let subtitle = wrap(&meta_font, subtitle, max_width, 1);
+    draw_text(&mut canvas, &font, META_SIZE, MARGIN, HEIGHT as f32 - MARGIN + META_SIZE / 2.0, subtitle.first().map(String::as_str).unwrap_or(""), CITE);
+    
+    let mut output = Vec::new();
+    canvas.write_to(&mut Cursor::new(&mut output), ImageFormat::Png)?;
+    Ok(output)
+}
+
+/// Renders the social preview image of a post. The image only depends on the metadata
+/// and the background, so unchanged images are taken from the image cache.
+pub fn render(metadata: &PostMetadata, background: Option<&[u8]>, options: &TransformOptions) -> Result<Vec<u8>> {
+    let date = metadata.date();
+    let mut subtitle = format!("{:02} {} {:04}", date.day(), date.month_name(), date.year());
+    
+    if !metadata.categories().is_empty() {
+        let categories: Vec<String> = metadata.categories().iter().map(|c| format!("#{c}")).collect();
+        subtitle.push_str(" · ");
+        subtitle.push_str(&categories.join(" "));
+    }
+    
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(metadata.title().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(subtitle.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(background.unwrap_or_default());
+    
+    transformer::cached_encode(options, &hasher.finalize(), WIDTH, "og.png", || draw(metadata.title(), &subtitle, background))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_wrap() {
+        let font = FontRef::try_from_slice(FONT).unwrap();
+        let font = font.as_scaled(PxScale::from(TITLE_SIZE));
+        let lines = wrap(&font, &"fuzzing ".repeat(40), 1040.0, TITLE_LINES);
+        assert_eq!(lines.len(), TITLE_LINES);
+        assert!(lines.last().unwrap().ends_with('…'));
+        assert!(lines.iter().all(|line| text_width(&font, line) <= 1040.0));
+    }
+}
@@ -3,7 +3,7 @@ use std::path::{PathBuf, Path};
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
 
-use crate::{posts::{PostMetadata, Post}, engine::Renderer, transformer};
+use crate::{posts::{PostMetadata, PostStats, Post, PostContext}, engine::Renderer, transformer::{self, ImageInfo, ImageOptions}, assets::AssetManifest, og, cite, fs};
 
 #[derive(Serialize, Deserialize, PartialEq ,Eq)]
 pub struct Dependency {
@@ -26,6 +26,12 @@ pub struct CacheEntry {
     dependencies: Vec<Dependency>,
     metadata: PostMetadata,
     url: String,
+    text: String,
+    summary: String,
+    stats: PostStats,
+    context: PostContext,
+    links: Vec<(PathBuf, String)>,
+    images: Vec<(PathBuf, ImageInfo)>,
 }
 
 impl CacheEntry {
@@ -40,11 +46,48 @@ impl CacheEntry {
     pub fn url(&self) -> &str {
         &self.url
     }
+    
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    
+    pub fn stats(&self) -> &PostStats {
+        &self.stats
+    }
+    
+    /// The description of the post or its first paragraph
+    pub fn summary(&self) -> &str {
+        &self.summary
+    }
+    
+    /// What the post showed about other posts when it was rendered
+    pub fn context(&self) -> &PostContext {
+        &self.context
+    }
+    
+    /// The linked posts and their urls when the post was rendered
+    pub fn links(&self) -> &[(PathBuf, String)] {
+        &self.links
+    }
+    
+    /// The images of the post whose source is older than their output,
+    /// so they need not be read again to render the post
+    pub fn known_images(&self, input_basedir: &Path) -> HashMap<PathBuf, ImageInfo> {
+        self.images.iter()
+            .filter(|(path, _)| {
+                let input = input_basedir.join(path);
+                self.dependencies.iter().any(|d| d.input == input && !fs::is_newer(&d.input, &d.output))
+            })
+            .cloned()
+            .collect()
+    }
 }
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct PostCache {
     resources: HashMap<PathBuf, CacheEntry>,
+    assets: AssetManifest,
+    image_options: ImageOptions,
 }
 
 impl PostCache {
@@ -56,24 +99,79 @@ impl PostCache {
         }
         
         let data = std::fs::read(path)?;
-        let cache = bitcode::deserialize(&data)?;
         
-        Ok(cache)
+        match bitcode::deserialize(&data) {
+            Ok(cache) => Ok(cache),
+            Err(_) => {
+                println!("Warning: The cache {} is outdated, rendering all posts", path.display());
+                Ok(Self::default())
+            },
+        }
     }
     
     pub fn clear(&mut self) {
         self.resources.clear();
     }
     
-    pub fn delete(&mut self, path: &PathBuf) -> bool {
-        self.resources.remove(path).is_some()
+    /// The static files that the cached posts were rendered with
+    pub fn assets(&self) -> &AssetManifest {
+        &self.assets
+    }
+    
+    pub fn set_assets(&mut self, assets: AssetManifest) {
+        self.assets = assets;
+    }
+    
+    /// The site-wide image settings that the cached posts were rendered with
+    pub fn image_options(&self) -> &ImageOptions {
+        &self.image_options
+    }
+    
+    pub fn set_image_options(&mut self, image_options: ImageOptions) {
+        self.image_options = image_options;
+    }
+    
+    pub fn delete(&mut self, path: &PathBuf) -> Result<bool> {
+        if let Some(old) = self.resources.remove(path) {
+            self.remove_stale_outputs(&old)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+    
+    /// Deletes all posts whose source no longer exists
+    pub fn prune(&mut self, inputs: &[PathBuf]) -> Result<bool> {
+        let deleted: Vec<PathBuf> = self.resources.keys()
+            .filter(|path| !inputs.contains(path))
+            .cloned()
+            .collect();
+        
+        for path in &deleted {
+            self.delete(path)?;
+        }
+        
+        Ok(!deleted.is_empty())
+    }
+    
+    /// Removes the outputs of an old entry that no current entry depends on
+    fn remove_stale_outputs(&self, old: &CacheEntry) -> Result<()> {
+        for dependency in &old.dependencies {
+            let in_use = self.resources.values().any(|entry| entry.dependencies.iter().any(|d| d.output == dependency.output));
+            
+            if !in_use {
+                transformer::remove_output(&dependency.output)?;
+            }
+        }
+        
+        Ok(())
     }
     
-    pub fn get(&mut self, path: &PathBuf) -> Option<&CacheEntry> {
+    pub fn get(&self, path: &PathBuf) -> Option<&CacheEntry> {
         self.resources.get(path)
     }
     
-    pub fn insert(&mut self, input_basedir: &Path, input_file: &Path, output_basedir: &Path, output_file: &Path, post: &Post, renderer: &Renderer) -> bool {
+    pub fn insert(&mut self, input_basedir: &Path, input_file: &Path, output_basedir: &Path, output_file: &Path, post: &Post, renderer: &Renderer) -> Result<bool> {
         let mut dependencies = vec![
             Dependency {
                 input: input_file.to_owned(),
@@ -81,9 +179,18 @@ impl PostCache {
             }
         ];
         
+        if post.filename().is_some() {
+            for output in [og::filename(output_file), cite::bibtex_filename(output_file), cite::csl_filename(output_file)] {
+                dependencies.push(Dependency {
+                    input: input_file.to_owned(),
+                    output,
+                });
+            }
+        }
+        
         for path in renderer.file_mentions() {
             let input = input_basedir.join(path);
-            let output = transformer::transform_filename(output_basedir.join(path));
+            let output = output_basedir.join(renderer.output_filename(path));
             
             dependencies.push(Dependency {
                 input,
@@ -95,6 +202,12 @@ impl PostCache {
             dependencies,
             metadata: post.metadata().clone(),
             url: post.url().to_owned(),
+            text: renderer.text().to_owned(),
+            summary: renderer.summary(post).to_owned(),
+            stats: renderer.stats(),
+            context: renderer.context().clone(),
+            links: renderer.links().iter().map(|(input, url)| (input.clone(), url.clone())).collect(),
+            images: renderer.image_info().iter().map(|(path, info)| (path.clone(), info.clone())).collect(),
         };
         
         let input_file = input_file.to_owned();
@@ -106,10 +219,12 @@ impl PostCache {
         };
         
         if changed {
-            self.resources.insert(input_file, entry);
+            if let Some(old) = self.resources.insert(input_file, entry) {
+                self.remove_stale_outputs(&old)?;
+            }
         }
         
-        changed
+        Ok(changed)
     }
     
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
@@ -118,7 +233,27 @@ impl PostCache {
         Ok(())
     }
     
-    pub fn resources(&self) -> Values<PathBuf, CacheEntry> {
+    pub fn resources(&self) -> Values<'_, PathBuf, CacheEntry> {
         self.resources.values()
     }
 }
+
+#[cfg(test)]
+impl PostCache {
+    /// Renders test posts into a cache like a build would
+    pub fn render(paths: &[&str]) -> Self {
+        let mut cache = Self::default();
+        let fixture = crate::engine::RenderFixture::default();
+        
+        for path in paths {
+            let input = Path::new(path);
+            let post = Post::new(input, true).unwrap();
+            let mut renderer = fixture.renderer(input.parent().unwrap(), true);
+            renderer.render_body(post.content()).unwrap();
+            let output = Path::new("/tmp/dove-test").join(post.filename().unwrap());
+            cache.insert(input.parent().unwrap(), input, output.parent().unwrap(), &output, &post, &renderer).unwrap();
+        }
+        
+        cache
+    }
+}
@@ -1,7 +1,11 @@
 mod iter;
 mod cache;
 mod post;
+mod stats;
+mod site;
 
 pub use iter::*;
 pub use cache::*;
 pub use post::*;
+pub use stats::*;
+pub use site::*;
@@ -1,6 +1,7 @@
 use memmap2::Mmap;
 use std::path::Path;
 use anyhow::Result;
+use chrono::Datelike;
 use serde::{Deserialize, Serialize};
 
 use crate::{parser::*, net::http_url_exists};
@@ -42,6 +43,65 @@ impl PostDate {
     pub fn year(&self) -> u16 {
         self.year
     }
+    
+    /// Parses a date in the format DD.MM.YYYY
+    pub fn parse(value: &str) -> Result<Self> {
+        parse_date(value.trim().as_bytes()).map_err(|msg| anyhow::anyhow!(msg))
+    }
+    
+    pub fn today() -> Self {
+        let today = chrono::Local::now().date_naive();
+        
+        Self {
+            year: today.year() as u16,
+            month: today.month() as u8,
+            day: today.day() as u8,
+        }
+    }
+    
+    /// Parses a date in the format YYYY-MM-DD
+    pub fn from_iso(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(3, '-').map(|x| x.parse::<u16>().ok());
+        let date = Self {
+            year: parts.next()??,
+            month: parts.next()?? as u8,
+            day: parts.next()?? as u8,
+        };
+        
+        if (1..=12).contains(&date.month) && (1..=31).contains(&date.day) {
+            Some(date)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostSeries {
+    name: String,
+    part: usize,
+}
+
+impl PostSeries {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    pub fn part(&self) -> usize {
+        self.part
+    }
+    
+    /// The url of the landing page of the series
+    pub fn url(&self) -> String {
+        format!("/series/{}", series_filename(&self.name))
+    }
+}
+
+/// A commit that touched the source file of a post
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostChange {
+    pub date: PostDate,
+    pub message: String,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -51,6 +111,13 @@ pub struct PostMetadata {
     startpage: bool,
     title: String,
     draft: bool,
+    toc: bool,
+    description: Option<String>,
+    updated: Option<PostDate>,
+    changelog: bool,
+    history: Vec<PostChange>,
+    unlisted: bool,
+    series: Option<PostSeries>,
 }
 
 impl PostMetadata {
@@ -70,8 +137,52 @@ impl PostMetadata {
         &self.title
     }
     
-    pub fn draft(&self) -> bool {
-        self.draft
+    /// Drafts and posts with a date in the future are not published yet
+    pub fn published(&self, now: &PostDate) -> bool {
+        !self.draft && self.date <= *now
+    }
+    
+    pub fn unlisted(&self) -> bool {
+        self.unlisted
+    }
+    
+    pub fn series(&self) -> Option<&PostSeries> {
+        self.series.as_ref()
+    }
+    
+    pub fn toc(&self) -> bool {
+        self.toc
+    }
+    
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+    
+    /// Changes to the post after its publication, newest first
+    pub fn changes(&self) -> impl Iterator<Item = &PostChange> {
+        self.history.iter().filter(|change| change.date > self.date)
+    }
+    
+    /// The history of the source file, newest first
+    pub fn history(&self) -> &[PostChange] {
+        &self.history
+    }
+    
+    pub fn set_history(&mut self, history: Vec<PostChange>) {
+        self.history = history;
+    }
+    
+    /// The date of the last update from the metadata or else from the history.
+    /// Posts that were never updated return their publication date.
+    pub fn updated(&self) -> &PostDate {
+        match &self.updated {
+            Some(updated) => updated,
+            None => self.changes().map(|change| &change.date).max().unwrap_or(&self.date),
+        }
+    }
+    
+    pub fn changelog(&self) -> bool {
+        self.changelog
     }
 }
 
@@ -128,6 +239,12 @@ impl Parser {
             anyhow::bail!("Metadata missing: categories");
         }
         
+        if let Some(updated) = &self.metadata.updated {
+            if *updated < self.metadata.date {
+                anyhow::bail!("The update date lies before the publication date");
+            }
+        }
+        
         /* Title constraints */
         if self.metadata.title.len() > 128 {
             anyhow::bail!("Title too long");
@@ -179,54 +296,23 @@ impl Parser {
         }
         
         match key {
-            b"date" => self.parse_date(value)?,
+            b"date" => self.metadata.date = parse_date(value)?,
+            b"updated" => self.metadata.updated = Some(parse_date(value)?),
+            b"changelog" => self.parse_changelog(value)?,
+            b"unlisted" => self.parse_unlisted(value)?,
+            b"series" => self.parse_series(value)?,
             b"categories" => self.parse_categories(value)?,
             b"startpage" => self.parse_startpage(value)?,
             b"mirror" => self.parse_mirror(value)?,
             b"draft" => self.parse_draft(value)?,
+            b"toc" => self.parse_toc(value)?,
+            b"description" => self.parse_description(value)?,
             _ => return Err("Invalid metadata. Attribute does not exist".to_string()),
         }
         
         Ok(true)
     }
     
-    fn parse_date(&mut self, value: &[u8]) -> Result<(), String> {
-        const SEPARATOR: u8 = b'.';
-        
-        let (day, rest) = split_once(value, SEPARATOR);
-        let (month, year) = split_once(rest, SEPARATOR);
-        
-        if day.len() != 2 || month.len() != 2 || year.len() != 4 {
-            return Err("Invalid date format. Must be DD.MM.YYYY".to_string());
-        }
-        
-        if !is_numerical(day) {
-            return Err("Invalid day".to_string());
-        }
-        if !is_numerical(month) {
-            return Err("Invalid month".to_string());
-        }
-        if !is_numerical(year) {
-            return Err("Invalid year".to_string());
-        }
-        
-        self.metadata.date.day = convert_number(day) as u8;
-        self.metadata.date.month = convert_number(month) as u8;
-        self.metadata.date.year = convert_number(year) as u16;
-        
-        if !(1..=31).contains(&self.metadata.date.day) {
-            return Err("Invalid day".to_string());
-        }
-        if !(1..=12).contains(&self.metadata.date.month) {
-            return Err("Invalid month".to_string());
-        }
-        if !(0..=9999).contains(&self.metadata.date.year) {
-            return Err("Invalid year".to_string());
-        }
-        
-        Ok(())
-    }
-    
     fn parse_categories(&mut self, value: &[u8]) -> Result<(), String> {
         const SEPARATOR: u8 = b',';
         let charsets = [b'a'..=b'z', b'0'..=b'9', b'-'..=b'-'];
@@ -278,6 +364,61 @@ impl Parser {
         Ok(())
     }
     
+    fn parse_toc(&mut self, value: &[u8]) -> Result<(), String> {
+        match value {
+            b"false" => self.metadata.toc = false,
+            b"true" => self.metadata.toc = true,
+            _ => return Err("Invalid boolean value".to_string()),
+        }
+        
+        Ok(())
+    }
+    
+    fn parse_series(&mut self, value: &[u8]) -> Result<(), String> {
+        let value = std::str::from_utf8(value).map_err(|_| "Series is not UTF-8".to_string())?;
+        let Some((name, part)) = value.rsplit_once(',') else {
+            return Err("Invalid series. Must be <name>, <part>".to_string());
+        };
+        let name = name.trim();
+        let part = part.trim().parse::<usize>().ok().filter(|part| *part > 0).ok_or("Invalid part number of series")?;
+        
+        if name.is_empty() {
+            return Err("Series name cannot be empty".to_string());
+        }
+        
+        self.metadata.series = Some(PostSeries {
+            name: name.to_owned(),
+            part,
+        });
+        Ok(())
+    }
+    
+    fn parse_unlisted(&mut self, value: &[u8]) -> Result<(), String> {
+        match value {
+            b"false" => self.metadata.unlisted = false,
+            b"true" => self.metadata.unlisted = true,
+            _ => return Err("Invalid boolean value".to_string()),
+        }
+        
+        Ok(())
+    }
+    
+    fn parse_changelog(&mut self, value: &[u8]) -> Result<(), String> {
+        match value {
+            b"false" => self.metadata.changelog = false,
+            b"true" => self.metadata.changelog = true,
+            _ => return Err("Invalid boolean value".to_string()),
+        }
+        
+        Ok(())
+    }
+    
+    fn parse_description(&mut self, value: &[u8]) -> Result<(), String> {
+        let value = std::str::from_utf8(value).map_err(|_| "Description is not UTF-8".to_string())?;
+        self.metadata.description = Some(value.to_owned());
+        Ok(())
+    }
+    
     fn parse_mirror(&mut self, value: &[u8]) -> Result<(), String> {
         let value = std::str::from_utf8(value).map_err(|_| "Invalid characters in URL".to_string())?;
         self.mirror = Some(value.to_owned());
@@ -285,6 +426,65 @@ impl Parser {
     }
 }
 
+fn parse_date(value: &[u8]) -> Result<PostDate, String> {
+    const SEPARATOR: u8 = b'.';
+    
+    let (day, rest) = split_once(value, SEPARATOR);
+    let (month, year) = split_once(rest, SEPARATOR);
+    
+    if day.len() != 2 || month.len() != 2 || year.len() != 4 {
+        return Err("Invalid date format. Must be DD.MM.YYYY".to_string());
+    }
+    
+    if !is_numerical(day) {
+        return Err("Invalid day".to_string());
+    }
+    if !is_numerical(month) {
+        return Err("Invalid month".to_string());
+    }
+    if !is_numerical(year) {
+        return Err("Invalid year".to_string());
+    }
+    
+    let date = PostDate {
+        day: convert_number(day) as u8,
+        month: convert_number(month) as u8,
+        year: convert_number(year) as u16,
+    };
+    
+    if !(1..=31).contains(&date.day) {
+        return Err("Invalid day".to_string());
+    }
+    if !(1..=12).contains(&date.month) {
+        return Err("Invalid month".to_string());
+    }
+    if !(0..=9999).contains(&date.year) {
+        return Err("Invalid year".to_string());
+    }
+    
+    Ok(date)
+}
+
+/// Names with characters outside of ASCII would lose them in the filename,
+/// so a hash of the full name keeps the filename non-empty and distinct
+fn series_filename(name: &str) -> String {
+    let encoded = encode_filename(name);
+    let slug = encoded.trim_end_matches(".html").trim_matches('-');
+    
+    if name.is_ascii() && !slug.is_empty() {
+        return format!("{slug}.html");
+    }
+    
+    let hash = blake3::hash(name.as_bytes()).to_hex();
+    let hash = &hash[..8];
+    
+    if slug.is_empty() {
+        format!("{hash}.html")
+    } else {
+        format!("{slug}-{hash}.html")
+    }
+}
+
 fn encode_filename(id: &str) -> String {
     let mut prev_dash = false;
     id.chars()
@@ -349,6 +549,10 @@ impl Post {
         &self.metadata
     }
     
+    pub fn metadata_mut(&mut self) -> &mut PostMetadata {
+        &mut self.metadata
+    }
+    
     pub fn url(&self) -> &str {
         &self.url
     }
@@ -378,4 +582,28 @@ mod tests {
         println!("{post:#?}");
         println!("{:?}", post.content());
     }
+    
+    #[test]
+    fn test_updated() {
+        let mut post = Post::new("test-data/postmeta/title.md", false).unwrap();
+        let date = post.metadata().date().clone();
+        assert_eq!(post.metadata().updated(), &date);
+        
+        let change = |date: &str| PostChange {
+            date: PostDate::from_iso(date).unwrap(),
+            message: String::new(),
+        };
+        post.metadata_mut().set_history(vec![change("9999-12-31"), change("0001-01-01")]);
+        assert_eq!(post.metadata().changes().count(), 1);
+        assert_eq!(post.metadata().updated(), &PostDate::from_iso("9999-12-31").unwrap());
+        assert_eq!(PostDate::from_iso("2024-13-01"), None);
+    }
+    
+    #[test]
+    fn test_series_filename() {
+        assert_eq!(series_filename("Fuzzing libpng"), "fuzzing-libpng.html");
+        assert!(series_filename("Über Fuzzing").starts_with("ber-fuzzing-"));
+        assert_ne!(series_filename("日本"), series_filename("中国"));
+        assert!(!series_filename("!!!").starts_with('.'));
+    }
 }
@@ -0,0 +1,278 @@
+use std::path::{Component, Path, PathBuf};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::posts::PostMetadata;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostLink {
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeriesPart {
+    pub part: usize,
+    pub title: String,
+    pub url: String,
+}
+
+/// The parts of the series that a post belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeriesContext {
+    pub name: String,
+    pub url: String,
+    pub part: usize,
+    pub parts: Vec<SeriesPart>,
+}
+
+impl SeriesContext {
+    pub fn previous(&self) -> Option<&SeriesPart> {
+        self.parts.iter().rev().find(|p| p.part < self.part)
+    }
+    
+    pub fn next(&self) -> Option<&SeriesPart> {
+        self.parts.iter().find(|p| p.part > self.part)
+    }
+}
+
+/// Everything a post shows about other posts. A post must be
+/// rendered again when its context changes.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostContext {
+    pub series: Option<SeriesContext>,
+    pub previous: Option<PostLink>,
+    pub next: Option<PostLink>,
+    pub related: Vec<PostLink>,
+    pub backlinks: Vec<PostLink>,
+}
+
+#[derive(Debug)]
+struct SitePost {
+    input: PathBuf,
+    url: String,
+    metadata: PostMetadata,
+    links: Vec<PathBuf>,
+}
+
+impl SitePost {
+    fn link(&self) -> PostLink {
+        PostLink {
+            title: self.metadata.title().to_owned(),
+            url: self.url.clone(),
+        }
+    }
+    
+    fn same_series(&self, other: &SitePost) -> bool {
+        match (self.metadata.series(), other.metadata.series()) {
+            (Some(a), Some(b)) => a.name() == b.name(),
+            _ => false,
+        }
+    }
+}
+
+/// Resolves `.` and `..` so that different paths to the same post compare equal
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            },
+            component => normalized.push(component),
+        }
+    }
+    
+    normalized
+}
+
+/// The metadata of all visible posts
+#[derive(Default, Debug)]
+pub struct Site {
+    posts: Vec<SitePost>,
+    related_posts: usize,
+}
+
+impl Site {
+    pub fn new(related_posts: usize) -> Self {
+        Self {
+            posts: Vec::new(),
+            related_posts,
+        }
+    }
+    
+    /// Adds a visible post. Fails when its series collides with another series or
+    /// when another post already is the same part of the series.
+    pub fn add(&mut self, input: &Path, metadata: &PostMetadata, url: &str) -> Result<()> {
+        if let Some(series) = metadata.series() {
+            for other in &self.posts {
+                let Some(other_series) = other.metadata.series() else {
+                    continue;
+                };
+                
+                if other_series.name() != series.name() && other_series.url() == series.url() {
+                    anyhow::bail!("The series '{}' and '{}' would have the same landing page {}", other_series.name(), series.name(), series.url());
+                } else if other_series.name() == series.name() && other_series.part() == series.part() {
+                    anyhow::bail!("{} and {} are both part {} of the series '{}'", other.input.display(), input.display(), series.part(), series.name());
+                }
+            }
+        }
+        
+        self.posts.push(SitePost {
+            input: normalize(input),
+            url: url.to_owned(),
+            metadata: metadata.clone(),
+            links: Vec::new(),
+        });
+        
+        Ok(())
+    }
+    
+    /// Sets the source files of the posts that a post links to
+    pub fn set_links(&mut self, input: &Path, links: &[PathBuf]) {
+        let input = normalize(input);
+        
+        if let Some(post) = self.posts.iter_mut().find(|post| post.input == input) {
+            post.links = links.iter().map(|link| normalize(link)).collect();
+        }
+    }
+    
+    /// The listed posts that link to a post in chronological order
+    fn backlinks(&self, post: &SitePost) -> Vec<PostLink> {
+        let mut backlinks: Vec<&SitePost> = self.posts.iter()
+            .filter(|other| other.input != post.input && !other.metadata.unlisted() && other.links.contains(&post.input))
+            .collect();
+        backlinks.sort_by(|a, b| a.metadata.date().cmp(b.metadata.date()).then_with(|| a.url.cmp(&b.url)));
+        backlinks.into_iter().map(SitePost::link).collect()
+    }
+    
+    fn series(&self, post: &SitePost) -> Option<SeriesContext> {
+        let series = post.metadata.series()?;
+        let mut parts: Vec<SeriesPart> = self.posts.iter()
+            .filter(|other| other.input == post.input || !other.metadata.unlisted())
+            .filter_map(|other| {
+                let other_series = other.metadata.series()?;
+                
+                if other_series.name() != series.name() {
+                    return None;
+                }
+                
+                Some(SeriesPart {
+                    part: other_series.part(),
+                    title: other.metadata.title().to_owned(),
+                    url: other.url.clone(),
+                })
+            })
+            .collect();
+        parts.sort_by(|a, b| a.part.cmp(&b.part).then_with(|| a.url.cmp(&b.url)));
+        
+        Some(SeriesContext {
+            name: series.name().to_owned(),
+            url: series.url(),
+            part: series.part(),
+            parts,
+        })
+    }
+    
+    /// The listed posts in chronological order
+    fn timeline(&self) -> Vec<&SitePost> {
+        let mut timeline: Vec<&SitePost> = self.posts.iter().filter(|post| !post.metadata.unlisted()).collect();
+        timeline.sort_by(|a, b| a.metadata.date().cmp(b.metadata.date()).then_with(|| a.url.cmp(&b.url)));
+        timeline
+    }
+    
+    /// Posts with the most categories in common, newer posts first.
+    /// Other parts of the same series are already linked by the series box.
+    fn related(&self, post: &SitePost) -> Vec<PostLink> {
+        let mut candidates: Vec<(usize, &SitePost)> = self.posts.iter()
+            .filter(|other| other.input != post.input && !other.metadata.unlisted() && !post.same_series(other))
+            .map(|other| {
+                let shared = other.metadata.categories().iter().filter(|c| post.metadata.categories().contains(c)).count();
+                (shared, other)
+            })
+            .filter(|(shared, _)| *shared > 0)
+            .collect();
+        
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.metadata.date().cmp(a.1.metadata.date())).then_with(|| a.1.url.cmp(&b.1.url)));
+        candidates.into_iter()
+            .take(self.related_posts)
+            .map(|(_, other)| other.link())
+            .collect()
+    }
+    
+    /// The url of the post with the given source file
+    pub fn url(&self, input: &Path) -> Option<&str> {
+        let input = normalize(input);
+        self.posts.iter().find(|post| post.input == input).map(|post| post.url.as_str())
+    }
+    
+    /// The source file of the post with the given title
+    pub fn find_title(&self, title: &str) -> Option<&Path> {
+        self.posts.iter()
+            .find(|post| post.metadata.title().eq_ignore_ascii_case(title.trim()))
+            .map(|post| post.input.as_path())
+    }
+    
+    /// Whether a link of a post no longer points to the current url of its target
+    pub fn links_changed(&self, links: &[(PathBuf, String)]) -> bool {
+        links.iter().any(|(input, url)| self.url(input) != Some(url.as_str()))
+    }
+    
+    pub fn context(&self, input: &Path) -> PostContext {
+        let input = normalize(input);
+        let Some(post) = self.posts.iter().find(|post| post.input == input) else {
+            return PostContext::default();
+        };
+        
+        let timeline = self.timeline();
+        let position = timeline.iter().position(|other| other.input == post.input);
+        let previous = position.and_then(|i| i.checked_sub(1)).map(|i| timeline[i].link());
+        let next = position.and_then(|i| timeline.get(i + 1)).map(|other| other.link());
+        
+        PostContext {
+            series: self.series(post),
+            previous,
+            next,
+            related: self.related(post),
+            backlinks: self.backlinks(post),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::posts::Post;
+    
+    #[test]
+    fn test_series() {
+        let mut site = Site::new(3);
+        
+        for path in ["test-data/series/two.md", "test-data/series/one.md"] {
+            let post = Post::new(path, true).unwrap();
+            site.add(Path::new(path), post.metadata(), post.url()).unwrap();
+        }
+        
+        let series = site.context(Path::new("test-data/series/one.md")).series.unwrap();
+        assert_eq!(series.name, "Fuzzing libpng");
+        assert_eq!(series.url, "/series/fuzzing-libpng.html");
+        assert_eq!(series.parts.iter().map(|p| p.part).collect::<Vec<_>>(), [1, 2]);
+        assert!(series.previous().is_none());
+        assert_eq!(series.next().unwrap().title, "Triaging Crashes");
+        
+        let context = site.context(Path::new("test-data/series/two.md"));
+        assert_eq!(context.previous.unwrap().title, "Building a Harness");
+        assert!(context.next.is_none());
+        assert!(context.related.is_empty());
+        
+        assert_eq!(site.url(Path::new("test-data/postmeta/../series/./two.md")), Some("/2024/feb/08/triaging-crashes.html"));
+        assert_eq!(site.find_title("building a harness"), Some(Path::new("test-data/series/one.md")));
+        
+        site.set_links(Path::new("test-data/series/two.md"), &[PathBuf::from("test-data/series/../series/one.md")]);
+        let backlinks = site.context(Path::new("test-data/series/one.md")).backlinks;
+        assert_eq!(backlinks.iter().map(|b| b.title.as_str()).collect::<Vec<_>>(), ["Triaging Crashes"]);
+        assert!(site.context(Path::new("test-data/series/two.md")).backlinks.is_empty());
+    }
+}
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+const WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostStats {
+    pub words: usize,
+    pub code_lines: usize,
+    pub figures: usize,
+    pub tables: usize,
+    pub citations: usize,
+}
+
+impl PostStats {
+    /// Reading time in minutes, rounded up
+    pub fn reading_time(&self) -> usize {
+        self.words.div_ceil(WORDS_PER_MINUTE).max(1)
+    }
+}
+
+impl std::ops::AddAssign<&PostStats> for PostStats {
+    fn add_assign(&mut self, other: &PostStats) {
+        self.words += other.words;
+        self.code_lines += other.code_lines;
+        self.figures += other.figures;
+        self.tables += other.tables;
+        self.citations += other.citations;
+    }
+}
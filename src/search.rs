@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::posts::CacheEntry;
+use crate::transformer::{self, TransformOptions};
+
+const MIN_TOKEN_LEN: usize = 2;
+
+#[derive(Serialize)]
+struct Document<'a> {
+    title: &'a str,
+    url: &'a str,
+}
+
+/// Lowercases text and splits it into alphanumeric tokens. Lowercasing can change
+/// the length and category of characters, so it must happen before splitting.
+/// Must stay in sync with tokenize() in archive.js.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| token.chars().count() >= MIN_TOKEN_LEN)
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Tokens are sharded by their first character so that a query
+/// only has to download the shards of its terms.
+fn shard_name(token: &str) -> char {
+    match token.chars().next() {
+        Some(c) if c.is_ascii_alphanumeric() => c,
+        _ => '_',
+    }
+}
+
+/// Writes an inverted index of all posts into <output_dir>/search:
+///   - docs.json: list of documents, the position is the document id
+///   - <shard>.json: maps tokens to a list of [document id, term frequency]
+///   - text/<id>.txt: plain text of a document for snippet generation
+pub fn write_index<P: AsRef<Path>>(entries: &[&CacheEntry], output_dir: P, options: &TransformOptions) -> Result<()> {
+    let search_dir = output_dir.as_ref().join("search");
+    let text_dir = search_dir.join("text");
+    
+    if search_dir.exists() {
+        std::fs::remove_dir_all(&search_dir)?;
+    }
+    std::fs::create_dir_all(&text_dir)?;
+    
+    let mut documents = Vec::with_capacity(entries.len());
+    let mut shards: BTreeMap<char, BTreeMap<String, Vec<(usize, usize)>>> = BTreeMap::new();
+    
+    for (id, entry) in entries.iter().enumerate() {
+        let title = entry.metadata().title();
+        let text = entry.text();
+        let mut frequencies: BTreeMap<String, usize> = BTreeMap::new();
+        
+        for token in tokenize(title).into_iter().chain(tokenize(text)) {
+            *frequencies.entry(token).or_default() += 1;
+        }
+        
+        for (token, count) in frequencies {
+            shards.entry(shard_name(&token))
+                .or_default()
+                .entry(token)
+                .or_default()
+                .push((id, count));
+        }
+        
+        transformer::write_output(&text_dir.join(format!("{id}.txt")), text.as_bytes(), options)?;
+        
+        documents.push(Document {
+            title,
+            url: entry.url(),
+        });
+    }
+    
+    transformer::write_output(&search_dir.join("docs.json"), &serde_json::to_vec(&documents)?, options)?;
+    
+    for (shard, postings) in shards {
+        transformer::write_output(&search_dir.join(format!("{shard}.json")), &serde_json::to_vec(&postings)?, options)?;
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize("Fuzzing the `libpng` parser: 2x faster, a-b"), ["fuzzing", "the", "libpng", "parser", "2x", "faster"]);
+        
+        /* The dotted capital I becomes "i" and a combining dot, which is not alphanumeric */
+        assert_eq!(tokenize("İstanbul"), ["stanbul"]);
+    }
+    
+    #[test]
+    fn test_write_index() {
+        let cache = crate::posts::PostCache::render(&["test-data/series/one.md"]);
+        let entries: Vec<&CacheEntry> = cache.resources().collect();
+        let output_dir = crate::fs::TestDir::new("search");
+        let options = TransformOptions {
+            precompress: true,
+            ..Default::default()
+        };
+        write_index(&entries, &output_dir, &options).unwrap();
+        
+        let shard: BTreeMap<String, Vec<(usize, usize)>> = serde_json::from_slice(&std::fs::read(output_dir.join("search/h.json")).unwrap()).unwrap();
+        assert_eq!(shard["harness"], [(0, 2)]);
+        
+        for file in ["docs.json", "h.json", "text/0.txt"] {
+            assert!(transformer::is_precompressed(output_dir.join("search").join(file), &options));
+        }
+    }
+}
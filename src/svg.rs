@@ -0,0 +1,431 @@
+/// The only elements that are kept. Everything else, like <script>, <foreignObject>
+/// or animations that could rewrite attributes, is removed together with its content.
+const ALLOWED_ELEMENTS: [&str; 48] = [
+    "svg", "g", "defs", "symbol", "use", "title", "desc", "a", "image", "style", "view",
+    "path", "rect", "circle", "ellipse", "line", "polyline", "polygon",
+    "text", "tspan", "textPath",
+    "marker", "pattern", "clipPath", "mask", "linearGradient", "radialGradient", "stop",
+    "filter", "feBlend", "feColorMatrix", "feComponentTransfer", "feComposite", "feConvolveMatrix",
+    "feDiffuseLighting", "feDisplacementMap", "feDistantLight", "feDropShadow", "feFlood",
+    "feFuncA", "feFuncB", "feFuncG", "feFuncR", "feGaussianBlur", "feMerge", "feMergeNode",
+    "feMorphology", "feOffset",
+];
+
+/// The only attributes that are kept. Event handlers and attributes of editors are not among them.
+const ALLOWED_ATTRIBUTES: [&str; 132] = [
+    "id", "class", "style", "transform", "lang", "xml:lang", "xml:space", "role", "aria-label",
+    "xmlns", "xmlns:xlink", "xmlns:svg", "version", "baseProfile", "viewBox", "preserveAspectRatio",
+    "href", "xlink:href", "xlink:title", "target",
+    "x", "y", "x1", "y1", "x2", "y2", "cx", "cy", "r", "rx", "ry", "fx", "fy", "fr", "dx", "dy",
+    "width", "height", "d", "points", "pathLength", "rotate", "textLength", "lengthAdjust",
+    "startOffset", "method", "spacing", "side",
+    "fill", "fill-opacity", "fill-rule", "stroke", "stroke-width", "stroke-linecap", "stroke-linejoin",
+    "stroke-miterlimit", "stroke-dasharray", "stroke-dashoffset", "stroke-opacity", "opacity",
+    "color", "display", "visibility", "overflow", "paint-order", "vector-effect", "mix-blend-mode",
+    "isolation", "shape-rendering", "text-rendering", "image-rendering", "color-interpolation",
+    "color-interpolation-filters",
+    "font-family", "font-size", "font-weight", "font-style", "font-variant", "font-stretch",
+    "text-anchor", "dominant-baseline", "alignment-baseline", "baseline-shift", "letter-spacing",
+    "word-spacing", "text-decoration", "writing-mode",
+    "offset", "stop-color", "stop-opacity", "gradientUnits", "gradientTransform", "spreadMethod",
+    "patternUnits", "patternContentUnits", "patternTransform", "clip-path", "clipPathUnits",
+    "clip-rule", "mask", "maskUnits", "maskContentUnits",
+    "marker-start", "marker-mid", "marker-end", "markerWidth", "markerHeight", "markerUnits",
+    "refX", "refY", "orient",
+    "filter", "filterUnits", "primitiveUnits", "in", "in2", "result", "stdDeviation", "mode",
+    "operator", "k1", "k2", "k3", "k4", "values", "type", "flood-color", "flood-opacity",
+    "lighting-color", "azimuth", "elevation", "scale",
+];
+
+/// Attributes whose value is a url
+const URL_ATTRIBUTES: [&str; 2] = ["href", "xlink:href"];
+
+/// Schemes that links and images may use. Everything else, like javascript:, is removed.
+const ALLOWED_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+/// Images that may be embedded as data urls
+const ALLOWED_DATA_URLS: [&str; 4] = ["data:image/png", "data:image/jpeg", "data:image/gif", "data:image/webp"];
+
+/// Elements whose whitespace is significant
+const TEXT_ELEMENTS: [&str; 3] = ["text", "tspan", "textPath"];
+
+struct Attribute<'a> {
+    key: &'a str,
+    value: Option<&'a str>,
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    closing: bool,
+    self_closing: bool,
+    attributes: Vec<Attribute<'a>>,
+    len: usize,
+}
+
+impl Tag<'_> {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|a| a.key == name)
+            .and_then(|a| a.value)
+    }
+}
+
+/// Parses the tag at the start of `data`, which must begin with '<'
+fn parse_tag(data: &str) -> Tag<'_> {
+    let bytes = data.as_bytes();
+    let mut pos = 1;
+    let closing = bytes.get(pos) == Some(&b'/');
+    
+    if closing {
+        pos += 1;
+    }
+    
+    let name_start = pos;
+    while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() && !matches!(bytes[pos], b'>' | b'/') {
+        pos += 1;
+    }
+    let name = &data[name_start..pos];
+    let mut attributes = Vec::new();
+    let mut self_closing = false;
+    
+    loop {
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        
+        match bytes.get(pos) {
+            None => break,
+            Some(b'>') => {
+                pos += 1;
+                break;
+            },
+            Some(b'/') => {
+                self_closing = true;
+                pos += 1;
+                continue;
+            },
+            _ => {},
+        }
+        
+        let key_start = pos;
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() && !matches!(bytes[pos], b'=' | b'>' | b'/') {
+            pos += 1;
+        }
+        let key = &data[key_start..pos];
+        let mut value = None;
+        
+        if bytes.get(pos) == Some(&b'=') {
+            pos += 1;
+            
+            match bytes.get(pos) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let value_start = pos + 1;
+                    let value_len = data[value_start..].find(quote as char).unwrap_or(data.len() - value_start);
+                    value = Some(&data[value_start..value_start + value_len]);
+                    pos = (value_start + value_len + 1).min(data.len());
+                },
+                _ => {
+                    let value_start = pos;
+                    while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() && bytes[pos] != b'>' {
+                        pos += 1;
+                    }
+                    value = Some(&data[value_start..pos]);
+                },
+            }
+        }
+        
+        attributes.push(Attribute {
+            key,
+            value,
+        });
+    }
+    
+    Tag {
+        name,
+        closing,
+        self_closing,
+        attributes,
+        len: pos,
+    }
+}
+
+/// Decodes character references so that encoded urls like `jav&#x61;script:` are recognized.
+/// Returns None for references that cannot be decoded, since a browser might still know them.
+fn decode_entities(value: &str) -> Option<String> {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        
+        let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '#')).unwrap_or(rest.len());
+        
+        if end == 0 || !rest[end..].starts_with(';') {
+            output.push('&');
+            continue;
+        }
+        
+        let entity = &rest[..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "colon" => Some(':'),
+            "Tab" => Some('\t'),
+            "NewLine" => Some('\n'),
+            _ => match entity.strip_prefix('#') {
+                Some(number) => match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => number.parse().ok(),
+                }.and_then(char::from_u32),
+                None => None,
+            },
+        };
+        
+        output.push(decoded?);
+        rest = &rest[end + 1..];
+    }
+    
+    output.push_str(rest);
+    Some(output)
+}
+
+/// Only fragments, relative urls, web links and raster images are safe to follow
+fn is_safe_url(value: &str) -> bool {
+    let Some(url) = decode_entities(value) else {
+        return false;
+    };
+    let url: String = url.chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    
+    let scheme_end = url.find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')));
+    
+    match scheme_end {
+        Some(end) if end > 0 && url[end..].starts_with(':') => {
+            let scheme = &url[..end];
+            
+            if scheme == "data" {
+                ALLOWED_DATA_URLS.iter().any(|prefix| url.starts_with(prefix))
+            } else {
+                ALLOWED_SCHEMES.contains(&scheme)
+            }
+        },
+        _ => true,
+    }
+}
+
+/// Elements must be in the svg namespace. Prefixed names of other namespaces
+/// could smuggle in html elements like <html:script>.
+fn is_allowed_element(name: &str) -> bool {
+    let name = name.strip_prefix("svg:").unwrap_or(name);
+    ALLOWED_ELEMENTS.contains(&name)
+}
+
+fn is_allowed_attribute(attribute: &Attribute) -> bool {
+    if !ALLOWED_ATTRIBUTES.contains(&attribute.key) {
+        return false;
+    }
+    
+    match attribute.value {
+        Some(value) if URL_ATTRIBUTES.contains(&attribute.key) => is_safe_url(value),
+        _ => true,
+    }
+}
+
+fn push_tag(output: &mut String, tag: &Tag) {
+    output.push('<');
+    
+    if tag.closing {
+        output.push('/');
+    }
+    
+    output.push_str(tag.name);
+    
+    for attribute in tag.attributes.iter().filter(|a| is_allowed_attribute(a)) {
+        output.push(' ');
+        output.push_str(attribute.key);
+        
+        if let Some(value) = attribute.value {
+            let quote = if value.contains('"') { '\'' } else { '"' };
+            output.push('=');
+            output.push(quote);
+            output.push_str(value);
+            output.push(quote);
+        }
+    }
+    
+    if tag.self_closing {
+        output.push('/');
+    }
+    
+    output.push('>');
+}
+
+fn push_text(output: &mut String, text: &str, inside_text: bool) {
+    if text.trim().is_empty() && !inside_text {
+        return;
+    }
+    
+    let mut last_whitespace = false;
+    
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_whitespace {
+                output.push(' ');
+            }
+            last_whitespace = true;
+        } else {
+            output.push(c);
+            last_whitespace = false;
+        }
+    }
+}
+
+/// Removes comments, processing instructions, metadata and insignificant whitespace from
+/// an svg document and keeps only elements and attributes that cannot execute scripts
+pub fn minify(data: &str) -> String {
+    let mut output = String::with_capacity(data.len());
+    let mut stack: Vec<&str> = Vec::new();
+    /* Number of open elements inside a removed element */
+    let mut removed_depth = 0;
+    let mut cursor = 0;
+    
+    while cursor < data.len() {
+        let rest = &data[cursor..];
+        
+        if !rest.starts_with('<') {
+            let len = rest.find('<').unwrap_or(rest.len());
+            
+            if removed_depth == 0 {
+                let inside_text = stack.iter().any(|name| TEXT_ELEMENTS.contains(name));
+                push_text(&mut output, &rest[..len], inside_text);
+            }
+            
+            cursor += len;
+        } else if rest.starts_with("<!--") {
+            cursor += rest.find("-->").map(|x| x + 3).unwrap_or(rest.len());
+        } else if rest.starts_with("<![CDATA[") {
+            let len = rest.find("]]>").map(|x| x + 3).unwrap_or(rest.len());
+            
+            if removed_depth == 0 {
+                output.push_str(&rest[..len]);
+            }
+            
+            cursor += len;
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            cursor += rest.find('>').map(|x| x + 1).unwrap_or(rest.len());
+        } else {
+            let tag = parse_tag(rest);
+            cursor += tag.len;
+            
+            if removed_depth > 0 {
+                if tag.closing {
+                    removed_depth -= 1;
+                } else if !tag.self_closing {
+                    removed_depth += 1;
+                }
+                continue;
+            }
+            
+            if !is_allowed_element(tag.name) {
+                /* Scripts are raw text that may contain '<', so skip to their end tag directly */
+                if tag.name.rsplit(':').next().is_some_and(|name| name.eq_ignore_ascii_case("script")) && !tag.closing && !tag.self_closing {
+                    let end_tag = format!("</{}", tag.name);
+                    cursor += data[cursor..].find(&end_tag).unwrap_or(data.len() - cursor);
+                    cursor += data[cursor..].find('>').map(|x| x + 1).unwrap_or(data.len() - cursor);
+                } else if !tag.closing && !tag.self_closing {
+                    removed_depth = 1;
+                }
+                continue;
+            }
+            
+            if tag.closing {
+                if let Some(pos) = stack.iter().rposition(|name| *name == tag.name) {
+                    stack.truncate(pos);
+                }
+            } else if !tag.self_closing {
+                stack.push(tag.name);
+            }
+            
+            push_tag(&mut output, &tag);
+        }
+    }
+    
+    output
+}
+
+/// Parses an absolute svg length like "120" or "120px"
+fn parse_length(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let value = value.strip_suffix("px").unwrap_or(value);
+    value.parse::<f64>().ok().filter(|x| *x > 0.0)
+}
+
+/// The intrinsic size of an svg from the width and height
+/// of its root element or from its viewBox
+pub fn dimensions(data: &str) -> Option<(u32, u32)> {
+    let start = data.find("<svg")?;
+    let tag = parse_tag(&data[start..]);
+    
+    let width = tag.attribute("width").and_then(parse_length);
+    let height = tag.attribute("height").and_then(parse_length);
+    
+    if let (Some(width), Some(height)) = (width, height) {
+        return Some((width.round() as u32, height.round() as u32));
+    }
+    
+    let view_box: Vec<f64> = tag.attribute("viewBox")?
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|x| !x.is_empty())
+        .filter_map(|x| x.parse().ok())
+        .collect();
+    
+    match view_box[..] {
+        [_, _, width, height] if width > 0.0 && height > 0.0 => Some((width.round() as u32, height.round() as u32)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_minify() {
+        let svg = "<?xml version=\"1.0\"?>\n<!-- comment -->\n<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 100 50\" onload=\"alert(1)\">\n  <script>alert(2)</script>\n  <a href=\"javascript:alert(3)\"><text x=\"1\">a  <tspan>b</tspan></text></a>\n  <rect width='10' height=\"10\"/>\n</svg>\n";
+        assert_eq!(
+            minify(svg),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 100 50\"><a><text x=\"1\">a <tspan>b</tspan></text></a><rect width=\"10\" height=\"10\"/></svg>"
+        );
+        assert_eq!(dimensions(svg), Some((100, 50)));
+    }
+    
+    #[test]
+    fn test_namespaced_script() {
+        let svg = "<svg:svg xmlns:svg=\"http://www.w3.org/2000/svg\"><svg:script>alert(1)</svg:script><svg:rect width=\"1\"/><h:script xmlns:h=\"http://www.w3.org/1999/xhtml\">if (a<b) alert(2)</h:script></svg:svg>";
+        assert_eq!(minify(svg), "<svg:svg xmlns:svg=\"http://www.w3.org/2000/svg\"><svg:rect width=\"1\"/></svg:svg>");
+    }
+    
+    #[test]
+    fn test_foreign_object() {
+        let svg = "<svg><foreignObject width=\"100\"><div xmlns=\"http://www.w3.org/1999/xhtml\"><iframe src=\"javascript:alert(1)\"></iframe><img src=\"x\" onerror=\"alert(2)\"/></div></foreignObject><circle r=\"1\"/></svg>";
+        assert_eq!(minify(svg), "<svg><circle r=\"1\"/></svg>");
+    }
+    
+    #[test]
+    fn test_encoded_urls() {
+        let svg = "<svg><a href=\"jav&#x61;script:alert(1)\"><text>a</text></a><a xlink:href=\"&#106;avascript&colon;alert(2)\"><text>b</text></a><a href=\" java\tscript:alert(3)\"/><a href=\"javascript&unknown;alert(5)\"/><image href=\"data:image/svg+xml,&lt;svg onload=alert(4)&gt;\"/><a href=\"https://example.com/#x\"/><image href=\"data:image/png;base64,AAAA\"/><use href=\"#icon\"/></svg>";
+        assert_eq!(minify(svg), "<svg><a><text>a</text></a><a><text>b</text></a><a/><a/><image/><a href=\"https://example.com/#x\"/><image href=\"data:image/png;base64,AAAA\"/><use href=\"#icon\"/></svg>");
+    }
+    
+    #[test]
+    fn test_animations() {
+        let svg = "<svg><a><animate attributeName=\"href\" values=\"javascript:alert(1)\"/><set attributeName=\"href\" to=\"javascript:alert(2)\"><set/></set><handler type=\"text/ecmascript\">alert(3)</handler><text>click</text></a></svg>";
+        assert_eq!(minify(svg), "<svg><a><text>click</text></a></svg>");
+    }
+}
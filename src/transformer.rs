@@ -1,12 +1,69 @@
 use std::path::{Path, PathBuf};
-use std::io::Cursor;
+use std::io::{Cursor, Write};
+use std::time::{Duration, SystemTime};
 use anyhow::Result;
+use flate2::{write::GzEncoder, Compression};
 use minify_html_onepass as minify_html;
-use image::ImageReader;
+use image::{ImageReader, DynamicImage, AnimationDecoder, Frame, imageops::FilterType, codecs::{avif::AvifEncoder, gif::GifDecoder}};
 use css_minify::optimizations as minify_css;
+use serde::{Deserialize, Serialize};
+
+use crate::{svg, metadata};
+
+const PRECOMPRESSED_EXTENSIONS: [&str; 2] = ["gz", "br"];
+const FINGERPRINT_LEN: usize = 6;
+const RESPONSIVE_WIDTHS: [u32; 3] = [480, 960, 1440];
+
+const AVIF_SPEED: u8 = 4;
+const IMAGE_CACHE_VERSION: u32 = 1;
+const PLACEHOLDER_WIDTH: u32 = 16;
+const PLACEHOLDER_QUALITY: f32 = 30.0;
+
+const JSON_LD_START: &str = "<script type=\"application/ld+json\">";
+const SCRIPT_END: &str = "</script>";
+
+/// The settings of a single image. Callers resolve them with `for_file` once
+/// and the transformer uses them as they are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageOptions {
+    pub quality: f32,
+    pub lossless: bool,
+    pub avif: bool,
+    pub passthrough: bool,
+    pub lossless_png: bool,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            quality: 85.0,
+            lossless: false,
+            avif: false,
+            passthrough: false,
+            lossless_png: false,
+        }
+    }
+}
+
+impl ImageOptions {
+    /// Applies the filename rules to the options of a single image
+    pub fn for_file<P: AsRef<Path>>(&self, path: P) -> Self {
+        let is_png = path.as_ref().extension().is_some_and(|x| x.eq_ignore_ascii_case("png"));
+        let mut options = self.clone();
+        options.lossless |= self.lossless_png && is_png;
+        options
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TransformOptions {
+    pub precompress: bool,
+    pub image: ImageOptions,
+    pub image_cache: Option<PathBuf>,
+}
 
 #[inline]
-fn is_image<P: AsRef<Path>>(path: P) -> bool {
+pub fn is_image<P: AsRef<Path>>(path: P) -> bool {
     matches!(
         path.as_ref().extension().map(|x| x.as_encoded_bytes()),
         Some(b"jpg") | Some(b"JPG") |
@@ -51,6 +108,142 @@ fn is_html<P: AsRef<Path>>(path: P) -> bool {
     )
 }
 
+#[inline]
+pub fn is_svg<P: AsRef<Path>>(path: P) -> bool {
+    matches!(
+        path.as_ref().extension().map(|x| x.as_encoded_bytes()),
+        Some(b"svg") | Some(b"SVG")
+    )
+}
+
+/// GIFs with more than one frame are converted into animated WebPs
+pub fn is_animated(data: &[u8]) -> bool {
+    if !data.starts_with(b"GIF8") {
+        return false;
+    }
+    
+    match GifDecoder::new(Cursor::new(data)) {
+        Ok(decoder) => decoder.into_frames().take(2).count() > 1,
+        Err(_) => false,
+    }
+}
+
+#[inline]
+fn is_webp<P: AsRef<Path>>(path: P) -> bool {
+    matches!(
+        path.as_ref().extension().map(|x| x.as_encoded_bytes()),
+        Some(b"webp")
+    )
+}
+
+/// Images that are already in a web format. They are published as they are, minus their metadata.
+#[inline]
+fn is_web_image<P: AsRef<Path>>(path: P) -> bool {
+    matches!(
+        path.as_ref().extension().map(|x| x.as_encoded_bytes()),
+        Some(b"webp") | Some(b"WEBP") |
+        Some(b"avif") | Some(b"AVIF")
+    )
+}
+
+#[inline]
+fn is_compressible<P: AsRef<Path>>(path: P) -> bool {
+    matches!(
+        path.as_ref().extension().map(|x| x.as_encoded_bytes()),
+        Some(b"html") | Some(b"HTML") |
+        Some(b"css") | Some(b"CSS") |
+        Some(b"js") | Some(b"JS") |
+        Some(b"xml") | Some(b"XML") |
+        Some(b"json") | Some(b"JSON") |
+        Some(b"bib") | Some(b"BIB") |
+        Some(b"txt") | Some(b"TXT") |
+        Some(b"svg") | Some(b"SVG")
+    )
+}
+
+#[inline]
+pub fn precompressed_filename<P: AsRef<Path>>(path: P, extension: &str) -> PathBuf {
+    let mut path = path.as_ref().as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// Checks whether the precompressed siblings of an output file exist
+/// if and only if they should exist
+pub fn is_precompressed<P: AsRef<Path>>(path: P, options: &TransformOptions) -> bool {
+    let path = path.as_ref();
+    let wanted = options.precompress && is_compressible(path);
+    
+    PRECOMPRESSED_EXTENSIONS.iter().all(|extension| precompressed_filename(path, extension).exists() == wanted)
+}
+
+fn remove_precompressed(path: &Path) -> Result<()> {
+    for extension in PRECOMPRESSED_EXTENSIONS {
+        let sibling = precompressed_filename(path, extension);
+        
+        if sibling.exists() {
+            std::fs::remove_file(sibling)?;
+        }
+    }
+    
+    Ok(())
+}
+
+/// Removes an output file together with its precompressed siblings
+/// and its downscaled variants
+pub fn remove_output<P: AsRef<Path>>(path: P) -> Result<()> {
+    let path = path.as_ref();
+    
+    remove_precompressed(path)?;
+    
+    if is_webp(path) {
+        let mut variants = vec![path.with_extension("avif")];
+        
+        for width in RESPONSIVE_WIDTHS {
+            let variant = responsive_filename(path, width);
+            variants.push(variant.with_extension("avif"));
+            variants.push(variant);
+        }
+        
+        for variant in variants {
+            if variant.exists() {
+                std::fs::remove_file(variant)?;
+            }
+        }
+    }
+    
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    
+    Ok(())
+}
+
+/// Writes a generated file as it is, together with its precompressed versions
+pub fn write_output(path: &Path, data: &[u8], options: &TransformOptions) -> Result<()> {
+    std::fs::write(path, data)?;
+    
+    if options.precompress && is_compressible(path) {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(data)?;
+        std::fs::write(precompressed_filename(path, "gz"), encoder.finish()?)?;
+        
+        let mut compressed = Vec::new();
+        let params = brotli::enc::BrotliEncoderParams {
+            quality: 11,
+            lgwin: 24,
+            ..Default::default()
+        };
+        brotli::BrotliCompress(&mut Cursor::new(data), &mut compressed, &params)?;
+        std::fs::write(precompressed_filename(path, "br"), compressed)?;
+    } else {
+        remove_precompressed(path)?;
+    }
+    
+    Ok(())
+}
+
 #[inline]
 fn transform_css_filename<P: AsRef<Path>>(path: P) -> PathBuf {
     let mut path = path.as_ref().to_owned();
@@ -72,8 +265,61 @@ fn transform_image_filename<P: AsRef<Path>>(path: P) -> PathBuf {
     path
 }
 
-pub fn transform_filename<P: AsRef<Path>>(filename: P) -> PathBuf {
-    if is_image(&filename) {
+/// Static css and js files get a content hash in their filename for cache busting
+pub fn is_fingerprinted<P: AsRef<Path>>(path: P) -> bool {
+    matches!(
+        path.as_ref().extension().map(|x| x.as_encoded_bytes()),
+        Some(b"css") | Some(b"js")
+    )
+}
+
+pub fn fingerprint(content: &[u8]) -> String {
+    let mut hash = blake3::hash(content).to_hex().to_string();
+    hash.truncate(FINGERPRINT_LEN);
+    hash
+}
+
+/// Inserts a fingerprint into a filename: post.css => post.3fa9c1.css, highlight.min.js => highlight.3fa9c1.min.js
+pub fn fingerprint_filename<P: AsRef<Path>>(path: P, fingerprint: &str) -> PathBuf {
+    let path = path.as_ref();
+    let name = path.file_name().unwrap().to_str().unwrap();
+    let split = name.find(".min.").or_else(|| name.rfind('.')).unwrap_or(name.len());
+    path.with_file_name(format!("{}.{}{}", &name[..split], fingerprint, &name[split..]))
+}
+
+/// Removes the fingerprint from a filename if it has one: post.3fa9c1.min.css => post.min.css
+pub fn strip_fingerprint(name: &str) -> Option<String> {
+    let mut parts: Vec<&str> = name.split('.').collect();
+    let position = parts.iter()
+        .skip(1)
+        .position(|part| part.len() == FINGERPRINT_LEN && part.bytes().all(|c| c.is_ascii_hexdigit()))?;
+    
+    if position + 2 >= parts.len() {
+        return None;
+    }
+    
+    parts.remove(position + 1);
+    Some(parts.join("."))
+}
+
+#[inline]
+fn responsive_filename<P: AsRef<Path>>(path: P, width: u32) -> PathBuf {
+    let path = path.as_ref();
+    let stem = path.file_stem().unwrap().to_str().unwrap();
+    path.with_file_name(format!("{stem}-{width}w.webp"))
+}
+
+/// Returns the filenames and widths of the downscaled versions that are
+/// generated for an image of the given width, not including the full-size image
+pub fn responsive_variants<P: AsRef<Path>>(path: P, width: u32) -> Vec<(PathBuf, u32)> {
+    RESPONSIVE_WIDTHS.iter()
+        .filter(|w| **w < width)
+        .map(|w| (responsive_filename(&path, *w), *w))
+        .collect()
+}
+
+pub fn transform_filename<P: AsRef<Path>>(filename: P, image: &ImageOptions) -> PathBuf {
+    if is_image(&filename) && !image.passthrough {
         transform_image_filename(filename)
     } else if is_css(&filename) {
         transform_css_filename(filename)
@@ -84,28 +330,307 @@ pub fn transform_filename<P: AsRef<Path>>(filename: P) -> PathBuf {
     }
 }
 
-pub fn transform_file<P1: AsRef<Path>, P2: AsRef<Path>>(infile: P1, outfile: P2) -> Result<()> {
-    let mut buffer = std::fs::read(infile)?;
-    transform_buffer(&mut buffer, outfile)
+fn encode_webp(image: &DynamicImage, options: &ImageOptions) -> Result<Vec<u8>> {
+    let encoder = match webp::Encoder::from_image(image) {
+        Ok(e) => e,
+        Err(msg) => anyhow::bail!("{msg}"),
+    };
+    
+    let data = if options.lossless {
+        encoder.encode_lossless()
+    } else {
+        encoder.encode(options.quality)
+    };
+    
+    Ok(data.to_vec())
 }
 
-pub fn transform_buffer<P: AsRef<Path>>(buffer: &mut [u8], outfile: P) -> Result<()> {    
-    if is_image(&outfile) {
-        let outfile = transform_image_filename(outfile);
+fn encode_animation(frames: &[Frame], options: &ImageOptions) -> Result<Vec<u8>> {
+    let Some(first) = frames.first() else {
+        anyhow::bail!("Animation has no frames");
+    };
+    let (width, height) = first.buffer().dimensions();
+    let Ok(mut config) = webp::WebPConfig::new() else {
+        anyhow::bail!("Could not initialize webp encoder");
+    };
+    config.lossless = options.lossless as i32;
+    config.alpha_compression = !options.lossless as i32;
+    config.quality = options.quality;
+    
+    let mut encoder = webp::AnimEncoder::new(width, height, &config);
+    let mut timestamp = 0;
+    
+    for frame in frames {
+        encoder.add_frame(webp::AnimFrame::from_rgba(frame.buffer(), width, height, timestamp));
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        timestamp += (numer / denom.max(1)) as i32;
+    }
+    
+    match encoder.try_encode() {
+        Ok(data) => Ok(data.to_vec()),
+        Err(err) => anyhow::bail!("Encoding animation failed: {err:?}"),
+    }
+}
+
+fn encode_avif(image: &DynamicImage, options: &ImageOptions) -> Result<Vec<u8>> {
+    let quality = if options.lossless {
+        100
+    } else {
+        options.quality as u8
+    };
+    let mut data = Vec::new();
+    let encoder = AvifEncoder::new_with_speed_quality(&mut data, AVIF_SPEED, quality);
+    image.write_with_encoder(encoder)?;
+    Ok(data)
+}
+
+/// Encoded images are stored under the hash of their source and encoder
+/// settings so that identical inputs are never encoded twice
+fn image_cache_path(options: &TransformOptions, source: &blake3::Hash, width: u32, extension: &str) -> Option<PathBuf> {
+    let dir = options.image_cache.as_ref()?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(source.as_bytes());
+    hasher.update(format!("{IMAGE_CACHE_VERSION}:{}:{}:{width}:{extension}", options.image.quality, options.image.lossless).as_bytes());
+    Some(dir.join(format!("{}.{extension}", hasher.finalize().to_hex())))
+}
+
+/// Returns the cached output of `encode` for the given source or runs it and caches the result.
+/// Every use refreshes the modification time of the entry so that `prune_image_cache` keeps it.
+pub fn cached_encode<F>(options: &TransformOptions, source: &blake3::Hash, width: u32, extension: &str, encode: F) -> Result<Vec<u8>>
+where
+    F: FnOnce() -> Result<Vec<u8>>,
+{
+    let Some(path) = image_cache_path(options, source, width, extension) else {
+        return encode();
+    };
+    
+    if let Ok(data) = std::fs::read(&path) {
+        if let Ok(file) = std::fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        return Ok(data);
+    }
+    
+    let data = encode()?;
+    
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    
+    /* An interrupted write must never leave a truncated entry behind */
+    let tmp = path.with_extension(format!("{extension}.{}.tmp", std::process::id()));
+    std::fs::write(&tmp, &data)?;
+    std::fs::rename(&tmp, &path)?;
+    
+    Ok(data)
+}
+
+/// Removes all entries of the image cache that were not used since `since`.
+/// Only call this after a build that encoded every image of the site.
+pub fn prune_image_cache(options: &TransformOptions, since: SystemTime) -> Result<()> {
+    let Some(dir) = &options.image_cache else {
+        return Ok(());
+    };
+    
+    if !dir.exists() {
+        return Ok(());
+    }
+    
+    /* Some filesystems store modification times in whole seconds */
+    let since = since - Duration::from_secs(2);
+    
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        
+        if entry.metadata()?.modified()? < since {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    
+    Ok(())
+}
+
+/// Images are only decoded when at least one of their outputs is not cached
+fn decode_once<'a>(image: &'a mut Option<DynamicImage>, buffer: &[u8]) -> Result<&'a DynamicImage> {
+    if image.is_none() {
+        *image = Some(ImageReader::new(Cursor::new(buffer)).with_guessed_format()?.decode()?);
+    }
+    
+    Ok(image.as_ref().unwrap())
+}
+
+/// What figures need to know about a source image
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageInfo {
+    pub dimensions: (u32, u32),
+    pub animated: bool,
+    pub placeholder: Option<String>,
+}
+
+/// Reads an image once to get its dimensions, whether it is animated and its placeholder
+pub fn inspect<P: AsRef<Path>>(path: P, options: &TransformOptions) -> Result<ImageInfo> {
+    let buffer = std::fs::read(path)?;
+    let dimensions = ImageReader::new(Cursor::new(&buffer)).with_guessed_format()?.into_dimensions()?;
+    
+    Ok(ImageInfo {
+        dimensions,
+        animated: is_animated(&buffer),
+        placeholder: placeholder(&buffer, options)?,
+    })
+}
+
+/// A tiny blurred version of an image as data url that is shown until the image has loaded.
+/// Images with transparency get no placeholder because it would shine through.
+fn placeholder(buffer: &[u8], options: &TransformOptions) -> Result<Option<String>> {
+    let data = cached_encode(options, &blake3::hash(buffer), PLACEHOLDER_WIDTH, "lqip", || {
         let image = ImageReader::new(Cursor::new(buffer)).with_guessed_format()?.decode()?;
-        let encoder = match webp::Encoder::from_image(&image) {
+        
+        if image.color().has_alpha() && image.to_rgba8().pixels().any(|p| p[3] < 255) {
+            return Ok(Vec::new());
+        }
+        
+        let thumbnail = image.resize(PLACEHOLDER_WIDTH, PLACEHOLDER_WIDTH, FilterType::Triangle).blur(0.5);
+        let thumbnail = DynamicImage::ImageRgb8(thumbnail.to_rgb8());
+        let encoder = match webp::Encoder::from_image(&thumbnail) {
             Ok(e) => e,
             Err(msg) => anyhow::bail!("{msg}"),
         };
-        let webp_data = encoder.encode(85.0);
-        std::fs::write(&outfile, &*webp_data)?;
+        Ok(encoder.encode(PLACEHOLDER_QUALITY).to_vec())
+    })?;
+    
+    if data.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(format!("data:image/webp;base64,{}", base64_simd::Base64::STANDARD.encode_to_boxed_str(&data))))
+    }
+}
+
+pub fn transform_file<P1: AsRef<Path>, P2: AsRef<Path>>(infile: P1, outfile: P2, options: &TransformOptions) -> Result<()> {
+    let mut buffer = std::fs::read(&infile)?;
+    
+    if is_image(&infile) || is_web_image(&infile) || is_svg(&infile) {
+        let kinds = metadata::sensitive_metadata(&buffer);
         
+        if !kinds.is_empty() {
+            println!("Warning: {} contains {} metadata which is stripped from the published image", infile.as_ref().display(), kinds.join(" and "));
+        }
+    }
+    
+    transform_buffer(&mut buffer, outfile, options)
+}
+
+/// Minifies an html page. Structured data is not javascript, so it is taken out
+/// before minification, compacted separately and put back into its empty script tags.
+fn minify_page(buffer: &[u8]) -> Result<Vec<u8>> {
+    let mut page = std::str::from_utf8(buffer)?.to_string();
+    let mut json_ld = Vec::new();
+    let mut cursor = 0;
+    
+    while let Some(start) = page[cursor..].find(JSON_LD_START) {
+        let start = cursor + start + JSON_LD_START.len();
+        let Some(end) = page[start..].find(SCRIPT_END) else {
+            anyhow::bail!("Unterminated JSON-LD script");
+        };
+        let value: serde_json::Value = serde_json::from_str(&page[start..start + end])?;
+        
+        /* Keep the data from closing the script tag */
+        json_ld.push(value.to_string().replace('<', "\\u003c").replace('>', "\\u003e"));
+        page.replace_range(start..start + end, "");
+        cursor = start;
+    }
+    
+    let cfg = minify_html::Cfg {
+        minify_js: true,
+        minify_css: true,
+    };
+    let mut minified = page.into_bytes();
+    let new_len = minify_html::in_place(&mut minified, &cfg)?;
+    minified.truncate(new_len);
+    
+    if json_ld.is_empty() {
+        return Ok(minified);
+    }
+    
+    let minified = String::from_utf8(minified)?;
+    let mut output = String::with_capacity(minified.len() + json_ld.iter().map(String::len).sum::<usize>());
+    let mut rest = minified.as_str();
+    
+    for data in json_ld {
+        let Some(start) = rest.find("application/ld+json") else {
+            anyhow::bail!("Minifier removed a JSON-LD script");
+        };
+        let end = start + rest[start..].find('>').unwrap_or(0) + 1;
+        output.push_str(&rest[..end]);
+        output.push_str(&data);
+        rest = &rest[end..];
+    }
+    
+    output.push_str(rest);
+    Ok(output.into_bytes())
+}
+
+pub fn transform_buffer<P: AsRef<Path>>(buffer: &mut [u8], outfile: P, options: &TransformOptions) -> Result<()> {    
+    if (is_image(&outfile) && options.image.passthrough) || is_web_image(&outfile) {
+        if metadata::is_avif(buffer) {
+            println!("Warning: The metadata of {} cannot be removed, it is published as it is", outfile.as_ref().display());
+            write_output(outfile.as_ref(), buffer, options)?;
+        } else {
+            write_output(outfile.as_ref(), &metadata::strip(buffer)?, options)?;
+        }
+    
+    } else if is_image(&outfile) && is_animated(buffer) {
+        let outfile = transform_image_filename(outfile);
+        let data = cached_encode(options, &blake3::hash(buffer), 0, "webp", || {
+            let frames = GifDecoder::new(Cursor::new(&*buffer))?.into_frames().collect_frames()?;
+            encode_animation(&frames, &options.image)
+        })?;
+        write_output(&outfile, &data, options)?;
+    
+    } else if is_image(&outfile) {
+        let outfile = transform_image_filename(outfile);
+        let source = blake3::hash(buffer);
+        let (width, height) = ImageReader::new(Cursor::new(&*buffer)).with_guessed_format()?.into_dimensions()?;
+        let mut image = None;
+        
+        let mut outputs = responsive_variants(&outfile, width);
+        outputs.push((outfile, width));
+        
+        for (path, output_width) in outputs {
+            let mut extensions = vec!["webp"];
+            
+            if options.image.avif {
+                extensions.push("avif");
+            }
+            
+            for extension in extensions {
+                let data = cached_encode(options, &source, output_width, extension, || {
+                    let image = decode_once(&mut image, buffer)?;
+                    let encode = |image: &DynamicImage| match extension {
+                        "avif" => encode_avif(image, &options.image),
+                        _ => encode_webp(image, &options.image),
+                    };
+                    
+                    if output_width == width {
+                        encode(image)
+                    } else {
+                        let output_height = (height as u64 * output_width as u64 / width as u64).max(1) as u32;
+                        encode(&image.resize_exact(output_width, output_height, FilterType::Lanczos3))
+                    }
+                })?;
+                write_output(&path.with_extension(extension), &data, options)?;
+            }
+        }
+    
+    } else if is_svg(&outfile) {
+        let str = std::str::from_utf8(buffer)?;
+        write_output(outfile.as_ref(), svg::minify(str).as_bytes(), options)?;
+    
     } else if is_css(&outfile) {
         let outfile = transform_css_filename(outfile);
         let str = std::str::from_utf8(buffer)?;
         let minified = minify_css::Minifier::default().minify(str, minify_css::Level::One).unwrap();
-        std::fs::write(&outfile, minified.as_bytes())?;
-        
+        write_output(&outfile, minified.as_bytes(), options)?;
+    
     } else if is_js(&outfile) {
         let outfile = transform_js_filename(outfile);
         let session = minify_js::Session::new();
@@ -113,19 +638,94 @@ pub fn transform_buffer<P: AsRef<Path>>(buffer: &mut [u8], outfile: P) -> Result
         if minify_js::minify(&session, minify_js::TopLevelMode::Global, buffer, &mut out).is_err() {
             anyhow::bail!("Minifying js file failed");
         }
-        std::fs::write(&outfile, &out)?;
-        
+        write_output(&outfile, &out, options)?;
+    
     } else if is_html(&outfile) {
-        let cfg = minify_html::Cfg {
-            minify_js: true,
-            minify_css: true,
-        };
-        let new_len = minify_html::in_place(buffer, &cfg)?;
-        std::fs::write(outfile.as_ref(), &buffer[..new_len])?;
-        
+        write_output(outfile.as_ref(), &minify_page(buffer)?, options)?;
+    
     } else {
-        std::fs::write(outfile.as_ref(), buffer)?;
+        write_output(outfile.as_ref(), buffer, options)?;
     }
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, RgbImage};
+    use crate::fs::TestDir;
+    
+    /// A small gradient image in the given format
+    fn test_image(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8, y as u8, (x ^ y) as u8]));
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+    
+    #[test]
+    fn test_image_cache() {
+        let dir = TestDir::new("image-cache");
+        let options = TransformOptions {
+            image_cache: Some(dir.join("cache")),
+            ..Default::default()
+        };
+        let source = blake3::hash(b"source");
+        let old = dir.join("cache/unused.webp");
+        
+        assert_eq!(cached_encode(&options, &source, 0, "webp", || Ok(vec![1, 2, 3])).unwrap(), [1, 2, 3]);
+        assert_eq!(cached_encode(&options, &source, 0, "webp", || anyhow::bail!("not cached")).unwrap(), [1, 2, 3]);
+        assert_eq!(std::fs::read_dir(dir.join("cache")).unwrap().count(), 1);
+        
+        std::fs::write(&old, [0]).unwrap();
+        std::fs::File::options().write(true).open(&old).unwrap().set_modified(SystemTime::now() - Duration::from_secs(3600)).unwrap();
+        prune_image_cache(&options, SystemTime::now() - Duration::from_secs(60)).unwrap();
+        assert!(!old.exists());
+        assert_eq!(cached_encode(&options, &source, 0, "webp", || anyhow::bail!("not cached")).unwrap(), [1, 2, 3]);
+    }
+    
+    #[test]
+    fn test_avif_is_copied() {
+        let dir = TestDir::new("avif");
+        let avif = b"\0\0\0\x1CftypavifAAAA".to_vec();
+        transform_buffer(&mut avif.clone(), dir.join("photo.avif"), &TransformOptions::default()).unwrap();
+        assert_eq!(std::fs::read(dir.join("photo.avif")).unwrap(), avif);
+    }
+    
+    #[test]
+    fn test_figure_overrides_lossless_png() {
+        let dir = TestDir::new("lossy");
+        let site = ImageOptions {
+            lossless_png: true,
+            ..Default::default()
+        };
+        let mut image = site.for_file("shot.png");
+        assert!(image.lossless);
+        
+        /* <figure-title lossy> */
+        image.lossless = false;
+        let options = TransformOptions {
+            image,
+            ..Default::default()
+        };
+        transform_buffer(&mut test_image(32, 32, ImageFormat::Png), dir.join("shot.png"), &options).unwrap();
+        let webp = std::fs::read(dir.join("shot.webp")).unwrap();
+        assert_eq!(&webp[12..16], b"VP8 ");
+    }
+    
+    #[test]
+    fn test_passthrough_svg_is_sanitized() {
+        let dir = TestDir::new("passthrough-svg");
+        let options = TransformOptions {
+            image: ImageOptions {
+                passthrough: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut svg = b"<svg onload=\"alert(1)\"><script>alert(2)</script><rect width=\"1\"/></svg>".to_vec();
+        transform_buffer(&mut svg, dir.join("a.svg"), &options).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("a.svg")).unwrap(), "<svg><rect width=\"1\"/></svg>");
+    }
+}
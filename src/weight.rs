@@ -0,0 +1,451 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::fmt::Write as _;
+use std::io::Write;
+use anyhow::Result;
+use flate2::{write::GzEncoder, Compression};
+
+use crate::transformer;
+
+const MAX_CONTRIBUTORS: usize = 5;
+
+pub struct PageWeight {
+    page: String,
+    total: u64,
+    resources: Vec<(String, u64)>,
+}
+
+impl PageWeight {
+    fn largest_contributors(&self) -> String {
+        self.resources.iter()
+            .take(MAX_CONTRIBUTORS)
+            .map(|(name, size)| format!("{name} ({})", format_size(*size)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[inline]
+fn format_size(size: u64) -> String {
+    format!("{:.1} KB", size as f64 / 1024.0)
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Tag<'a> {
+    fn attribute(&self, name: &str) -> Option<&'a str> {
+        self.attributes.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Returns all opening tags of a (possibly minified) html document
+/// and the contents of all <style> elements.
+fn parse_html(html: &str) -> (Vec<Tag<'_>>, Vec<&str>) {
+    let mut tags = Vec::new();
+    let mut styles = Vec::new();
+    let mut cursor = 0;
+    
+    while let Some(offset) = html[cursor..].find('<') {
+        cursor += offset + 1;
+        let rest = &html[cursor..];
+        
+        if rest.starts_with("!--") {
+            cursor += rest.find("-->").unwrap_or(rest.len());
+            continue;
+        } else if !rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue;
+        }
+        
+        let name_len = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+        let name = &rest[..name_len];
+        let mut attributes = Vec::new();
+        let mut pos = name_len;
+        let bytes = rest.as_bytes();
+        
+        loop {
+            while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'/') {
+                pos += 1;
+            }
+            
+            if pos >= bytes.len() || bytes[pos] == b'>' {
+                break;
+            }
+            
+            let key_start = pos;
+            while pos < bytes.len() && !matches!(bytes[pos], b'=' | b'>' | b'/') && !bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            let key = &rest[key_start..pos];
+            let mut value = "";
+            
+            if bytes.get(pos) == Some(&b'=') {
+                pos += 1;
+                
+                match bytes.get(pos) {
+                    Some(&quote) if quote == b'"' || quote == b'\'' => {
+                        let value_start = pos + 1;
+                        let value_len = rest[value_start..].find(quote as char).unwrap_or(rest.len() - value_start);
+                        value = &rest[value_start..value_start + value_len];
+                        pos = value_start + value_len + 1;
+                    },
+                    _ => {
+                        let value_start = pos;
+                        while pos < bytes.len() && bytes[pos] != b'>' && !bytes[pos].is_ascii_whitespace() {
+                            pos += 1;
+                        }
+                        value = &rest[value_start..pos];
+                    },
+                }
+            }
+            
+            if !key.is_empty() {
+                attributes.push((key, value));
+            }
+        }
+        
+        cursor += pos.min(rest.len());
+        
+        /* Skip over raw text elements */
+        if name.eq_ignore_ascii_case("style") || name.eq_ignore_ascii_case("script") {
+            let end_tag = format!("</{}", name.to_ascii_lowercase());
+            let end = html[cursor..].find(&end_tag).unwrap_or(html.len() - cursor);
+            
+            if name.eq_ignore_ascii_case("style") {
+                styles.push(&html[cursor..cursor + end]);
+            }
+            
+            cursor += end;
+        }
+        
+        tags.push(Tag {
+            name,
+            attributes,
+        });
+    }
+    
+    (tags, styles)
+}
+
+/// Extracts the arguments of all url() functions in a stylesheet
+fn css_urls(css: &str) -> Vec<&str> {
+    let mut urls = Vec::new();
+    let mut cursor = 0;
+    
+    while let Some(offset) = css[cursor..].find("url(") {
+        cursor += offset + 4;
+        let end = css[cursor..].find(')').unwrap_or(css.len() - cursor);
+        let url = css[cursor..cursor + end].trim().trim_matches(|c| c == '"' || c == '\'');
+        urls.push(url);
+        cursor += end;
+    }
+    
+    urls
+}
+
+/// Extracts the urls of the candidates in a srcset like "a-480.webp 480w, a.webp 960w"
+fn srcset_urls(srcset: &str) -> impl Iterator<Item = &str> {
+    srcset.split(',').filter_map(|candidate| candidate.split_whitespace().next())
+}
+
+/// Returns the resources that a browser downloads when loading the page. Each entry lists
+/// alternatives of which the browser only downloads one: the candidates of a srcset or
+/// all sources of a <picture> together with its <img>.
+fn html_urls(html: &str) -> Vec<Vec<&str>> {
+    let (tags, styles) = parse_html(html);
+    let mut urls = Vec::new();
+    let mut picture: Option<Vec<&str>> = None;
+    
+    for tag in &tags {
+        let mut alternatives = Vec::new();
+        
+        match tag.name.to_ascii_lowercase().as_str() {
+            "link" => {
+                let rel = tag.attribute("rel").unwrap_or("").to_ascii_lowercase();
+                
+                if rel.split_whitespace().any(|r| matches!(r, "stylesheet" | "preload" | "icon")) {
+                    alternatives.extend(tag.attribute("href"));
+                }
+            },
+            "picture" => picture = Some(Vec::new()),
+            "img" | "source" => {
+                alternatives.extend(tag.attribute("src"));
+                alternatives.extend(tag.attribute("srcset").into_iter().flat_map(srcset_urls));
+            },
+            "script" | "video" | "audio" => alternatives.extend(tag.attribute("src")),
+            _ => {},
+        }
+        
+        match &mut picture {
+            /* The <img> is the last child of a <picture> */
+            Some(sources) if tag.name.eq_ignore_ascii_case("img") => {
+                sources.extend(alternatives);
+                urls.push(picture.take().unwrap());
+            },
+            Some(sources) => sources.extend(alternatives),
+            None if !alternatives.is_empty() => urls.push(alternatives),
+            None => {},
+        }
+    }
+    
+    for style in styles {
+        urls.extend(css_urls(style).into_iter().map(|url| vec![url]));
+    }
+    
+    urls
+}
+
+fn resolve_url(url: &str, output_dir: &Path, base_dir: &Path) -> Option<PathBuf> {
+    if url.is_empty() || url.starts_with('#') || url.starts_with("//") || url.starts_with("data:") || url.contains("://") {
+        return None;
+    }
+    
+    let url = url.split(['?', '#']).next().unwrap();
+    let (mut path, url) = match url.strip_prefix('/') {
+        Some(url) => (output_dir.to_owned(), url),
+        None => (base_dir.to_owned(), url),
+    };
+    
+    for component in Path::new(url).components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                path.pop();
+            },
+            component => path.push(component),
+        }
+    }
+    
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+struct SizeCache {
+    compressed: bool,
+    sizes: HashMap<PathBuf, u64>,
+}
+
+impl SizeCache {
+    fn size(&mut self, path: &Path) -> Result<u64> {
+        if let Some(size) = self.sizes.get(path) {
+            return Ok(*size);
+        }
+        
+        /* Files that were precompressed are served as they are on disk */
+        let gz = transformer::precompressed_filename(path, "gz");
+        
+        let size = if self.compressed && gz.is_file() {
+            gz.metadata()?.len()
+        } else if self.compressed {
+            let content = std::fs::read(path)?;
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(&content)?;
+            encoder.finish()?.len() as u64
+        } else {
+            path.metadata()?.len()
+        };
+        
+        self.sizes.insert(path.to_owned(), size);
+        Ok(size)
+    }
+}
+
+fn display_name(path: &Path, output_dir: &Path) -> String {
+    match path.strip_prefix(output_dir) {
+        Ok(path) => format!("/{}", path.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+/// Returns all html files under the output folder so that every generated page is measured
+pub fn html_pages<P: AsRef<Path>>(output_dir: P) -> Result<Vec<PathBuf>> {
+    let mut pages = Vec::new();
+    let mut dirs = vec![output_dir.as_ref().to_owned()];
+    
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|x| x == "html") {
+                pages.push(path);
+            }
+        }
+    }
+    
+    pages.sort();
+    Ok(pages)
+}
+
+/// Computes the transfer weight of each page: the page itself plus every
+/// stylesheet, script, font and image it references from the output folder.
+/// Of responsive images only the largest candidate is counted.
+pub fn measure_pages<P: AsRef<Path>>(output_dir: P, pages: &[PathBuf], compressed: bool) -> Result<Vec<PageWeight>> {
+    let output_dir = output_dir.as_ref();
+    let mut sizes = SizeCache {
+        compressed,
+        sizes: HashMap::new(),
+    };
+    let mut weights = Vec::with_capacity(pages.len());
+    
+    for page in pages {
+        if !page.exists() {
+            continue;
+        }
+        
+        let mut visited = HashSet::new();
+        let mut queue = vec![page.clone()];
+        let mut resources = Vec::new();
+        
+        while let Some(path) = queue.pop() {
+            if !visited.insert(path.clone()) {
+                continue;
+            }
+            
+            let base_dir = path.parent().unwrap_or(output_dir);
+            let extension = path.extension().and_then(|x| x.to_str()).unwrap_or("");
+            
+            if path == *page {
+                let html = std::fs::read_to_string(&path)?;
+                
+                for alternatives in html_urls(&html) {
+                    let mut largest: Option<(u64, PathBuf)> = None;
+                    
+                    for path in alternatives.into_iter().filter_map(|url| resolve_url(url, output_dir, base_dir)) {
+                        let size = sizes.size(&path)?;
+                        
+                        if largest.as_ref().is_none_or(|(largest, _)| size > *largest) {
+                            largest = Some((size, path));
+                        }
+                    }
+                    
+                    queue.extend(largest.map(|(_, path)| path));
+                }
+            } else if extension.eq_ignore_ascii_case("css") {
+                let css = std::fs::read_to_string(&path)?;
+                queue.extend(css_urls(&css).into_iter().filter_map(|url| resolve_url(url, output_dir, base_dir)));
+            }
+            
+            resources.push((display_name(&path, output_dir), sizes.size(&path)?));
+        }
+        
+        resources.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        
+        weights.push(PageWeight {
+            page: display_name(page, output_dir),
+            total: resources.iter().map(|(_, size)| size).sum(),
+            resources,
+        });
+    }
+    
+    weights.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.page.cmp(&b.page)));
+    
+    Ok(weights)
+}
+
+pub fn write_report<P: AsRef<Path>>(path: P, weights: &[PageWeight]) -> Result<()> {
+    let mut report = String::with_capacity(4096);
+    
+    for weight in weights {
+        writeln!(&mut report, "{:>10}  {}", format_size(weight.total), weight.page)?;
+        
+        for (name, size) in &weight.resources {
+            writeln!(&mut report, "    {:>10}  {}", format_size(*size), name)?;
+        }
+        
+        writeln!(&mut report)?;
+    }
+    
+    if !weights.is_empty() {
+        let average = weights.iter().map(|w| w.total).sum::<u64>() / weights.len() as u64;
+        writeln!(&mut report, "Average page weight: {} over {} pages", format_size(average), weights.len())?;
+    }
+    
+    std::fs::write(path, report)?;
+    Ok(())
+}
+
+pub fn check_budget(weights: &[PageWeight], budget: u64) -> Result<()> {
+    let mut message = String::new();
+    
+    for weight in weights {
+        if weight.total > budget {
+            writeln!(
+                &mut message,
+                "Page {} weighs {} which exceeds the budget of {}. Largest contributors: {}",
+                weight.page,
+                format_size(weight.total),
+                format_size(budget),
+                weight.largest_contributors(),
+            )?;
+        }
+    }
+    
+    if !message.is_empty() {
+        anyhow::bail!("{}", message.trim_end());
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_html_urls() {
+        let html = r#"<!DOCTYPE html><html><head><link rel=stylesheet href=/css/post.min.css><link href="/atom.xml" rel="alternate"><style>@font-face{src:url("/fonts/code.woff2")}</style><script src='/js/highlight.min.js' defer></script></head><body><a href="/"><img loading=lazy src=./a.webp></a><!-- <img src="b.webp"> --></body></html>"#;
+        let urls = html_urls(html);
+        assert_eq!(urls, [vec!["/css/post.min.css"], vec!["/js/highlight.min.js"], vec!["./a.webp"], vec!["/fonts/code.woff2"]]);
+    }
+    
+    #[test]
+    fn test_responsive_urls() {
+        let html = r#"<p><img src=a.webp srcset="a-480.webp 480w, a-960.webp 960w, a.webp 1200w" sizes=100vw><picture><source type=image/avif srcset="b-480.avif 480w,b.avif 960w"><img src=b.webp srcset="b-480.webp 480w, b.webp 960w"></picture>"#;
+        let urls = html_urls(html);
+        assert_eq!(urls, [
+            vec!["a.webp", "a-480.webp", "a-960.webp", "a.webp"],
+            vec!["b-480.avif", "b.avif", "b.webp", "b-480.webp", "b.webp"],
+        ]);
+    }
+    
+    #[test]
+    fn test_html_pages() {
+        let dir = crate::fs::TestDir::new("html-pages");
+        std::fs::create_dir_all(dir.join("series")).unwrap();
+        
+        for name in ["index.html", "index.html.gz", "series/fuzzing.html", "feed.json"] {
+            std::fs::write(dir.join(name), []).unwrap();
+        }
+        
+        assert_eq!(html_pages(&dir).unwrap(), [dir.join("index.html"), dir.join("series/fuzzing.html")]);
+    }
+    
+    #[test]
+    fn test_measure_responsive_page() {
+        let dir = crate::fs::TestDir::new("weight");
+        
+        let page = dir.join("page.html");
+        std::fs::write(&page, r#"<picture><source type=image/avif srcset="b-480.avif 480w, b.avif 960w"><img src=b.webp srcset="b-480.webp 480w, b.webp 960w"></picture>"#).unwrap();
+        std::fs::write(transformer::precompressed_filename(&page, "gz"), [0; 10]).unwrap();
+        
+        for (name, size) in [("b-480.avif", 100), ("b.avif", 300), ("b-480.webp", 200), ("b.webp", 400)] {
+            std::fs::write(dir.join(name), vec![0; size]).unwrap();
+        }
+        
+        let weights = measure_pages(&dir, std::slice::from_ref(&page), false).unwrap();
+        assert_eq!(weights[0].resources.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["/b.webp", "/page.html"]);
+        
+        /* The precompressed page is measured by its .gz sibling */
+        let weights = measure_pages(&dir, &[page], true).unwrap();
+        assert_eq!(weights[0].resources.iter().find(|(name, _)| name == "/page.html").unwrap().1, 10);
+    }
+}
@@ -47,6 +47,107 @@ function parseFilter(filter) {
     };
 }
 
+const SNIPPET_CONTEXT = 80;
+const searchCache = {};
+let searchGeneration = 0;
+
+/* Must stay in sync with search::tokenize() in dove, which splits at characters that are not char::is_alphanumeric() */
+function tokenize(text) {
+    const tokens = text.toLowerCase().match(/[\p{Alphabetic}\p{N}]+/gu) ?? [];
+    return tokens.filter((token) => [...token].length >= 2);
+}
+
+function shardName(token) {
+    return /^[a-z0-9]/.test(token) ? token[0] : "_";
+}
+
+function fetchCached(url, json) {
+    if (!(url in searchCache)) {
+        searchCache[url] = fetch(url).then((response) => {
+            if (!response.ok) {
+                return json ? {} : "";
+            }
+            
+            return json ? response.json() : response.text();
+        });
+    }
+    
+    return searchCache[url];
+}
+
+async function searchText(terms) {
+    const docs = await fetchCached("/search/docs.json", true);
+    let scores = null;
+    
+    for (const term of terms) {
+        const shard = await fetchCached(`/search/${shardName(term)}.json`, true);
+        const matches = new Map();
+        
+        /* Terms match all tokens they are a prefix of */
+        for (const [token, postings] of Object.entries(shard)) {
+            if (token.startsWith(term)) {
+                for (const [id, count] of postings) {
+                    matches.set(id, (matches.get(id) ?? 0) + count);
+                }
+            }
+        }
+        
+        if (scores === null) {
+            scores = matches;
+        } else {
+            for (const id of [...scores.keys()]) {
+                if (matches.has(id)) {
+                    scores.set(id, scores.get(id) + matches.get(id));
+                } else {
+                    scores.delete(id);
+                }
+            }
+        }
+    }
+    
+    const results = new Map();
+    
+    for (const id of scores.keys()) {
+        results.set(docs[id].url, id);
+    }
+    
+    return results;
+}
+
+async function makeSnippet(id, terms) {
+    const text = (await fetchCached(`/search/text/${id}.txt`, false)).replace(/\s+/g, " ");
+    const lower = text.toLowerCase();
+    let pos = -1;
+    let len = 0;
+    
+    for (const term of terms) {
+        const candidate = lower.indexOf(term);
+        
+        if (candidate >= 0 && (pos < 0 || candidate < pos)) {
+            pos = candidate;
+            len = term.length;
+        }
+    }
+    
+    if (pos < 0) {
+        return null;
+    }
+    
+    const start = Math.max(0, pos - SNIPPET_CONTEXT);
+    const end = Math.min(text.length, pos + len + SNIPPET_CONTEXT);
+    const snippet = document.createDocumentFragment();
+    const mark = document.createElement("mark");
+    mark.textContent = text.substring(pos, pos + len);
+    
+    snippet.append(
+        (start > 0 ? "…" : "") + text.substring(start, pos),
+        mark,
+        text.substring(pos + len, end) + (end < text.length ? "…" : ""),
+    );
+    
+    return snippet;
+}
+
 function isWhitespace(c) {
     return c === " " || c === "\t";
 }
@@ -98,9 +199,12 @@ function splitQuery(query) {
     return tokens;
 }
 
-function applyFilters(filters) {
+function applyFilters(filters, results, terms) {
     for (elem of document.getElementsByClassName("post-entry")) {
-        let show_elem = true;
+        let show_elem = results === null || results.has(elem.getAttribute("data-url"));
+        const snippet = elem.getElementsByClassName("post-snippet")[0];
+        
+        snippet.replaceChildren();
         
         for (filter of filters) {
             if (!show_elem) {
@@ -124,31 +228,59 @@ function applyFilters(filters) {
         
         if (show_elem) {
             elem.style.setProperty("display", "block");
+            
+            if (results !== null) {
+                const generation = searchGeneration;
+                
+                makeSnippet(results.get(elem.getAttribute("data-url")), terms).then((content) => {
+                    if (content !== null && generation === searchGeneration) {
+                        snippet.replaceChildren(content);
+                    }
+                });
+            }
         } else {
             elem.style.setProperty("display", "none");
         }
     }
 }
 
-function parseQuery(query) {
+async function parseQuery(query) {
     const tokens = splitQuery(query);
+    const generation = ++searchGeneration;
     let filters = [];
+    let terms = [];
     
     for (token of tokens) {
         const filter = parseFilter(token);
         
-        if (filter === null) {
+        if (filter !== null) {
+            filters.push(filter);
+            continue;
+        }
+        
+        const token_terms = tokenize(token);
+        
+        /* Words that are too short for the index still filter the titles */
+        if (token_terms.length > 0) {
+            terms.push(...token_terms);
+        } else {
             filters.push({
                 exclude: false,
                 key: "title",
                 value: token.toLowerCase(),
             });
-        } else {
-            filters.push(filter);
         }
     }
     
-    applyFilters(filters);
+    let results = null;
+    
+    if (terms.length > 0) {
+        results = await searchText(terms);
+    }
+    
+    if (generation === searchGeneration) {
+        applyFilters(filters, results, terms);
+    }
 }
 
 document.addEventListener("DOMContentLoaded", () => {